@@ -1,30 +1,52 @@
-use std::{
-    hash::{DefaultHasher, Hash, Hasher},
-    time::Duration,
-};
+use std::time::Duration;
 
-use shared_lib::structs::{
-    discord::{DiscordWebhook, DiscordWebhookBody},
-    sonarr::{SonarrGroupKey, SonarrRequestBody},
+use shared_lib::{
+    debounce::{flush_delay, DEFAULT_DEBOUNCE, DEFAULT_MAX_WAIT},
+    destination::Destination,
+    error::HookbufferError,
+    retry::RetryPolicy,
+    source::{build_webhook, group_id, stable_hash, Delivery, Lidarr, Radarr, Sonarr, Source},
+    structs::{
+        dead_letter::DeadLetter,
+        discord::{DiscordWebhook, RenderStyle},
+    },
+    template::Templates,
 };
 use wasm_bindgen::JsValue;
 use worker::*;
 
+// name of the durable object that keeps track of which queues have something in them
+// and which destinations are paused, for the admin API
+const REGISTRY: &str = "hookbuffer-admin-registry";
+// how often an alarm for a paused destination checks if it's been resumed
+const PAUSE_RECHECK: Duration = Duration::from_secs(60);
+// pause between sends in the queue consumer
+const SEND_SPACING: Duration = Duration::from_secs(1);
+// the queue the outbound queue moves webhooks to once it's out of retries, see wrangler.toml
+const DEAD_LETTER_QUEUE: &str = "hookbuffer-dead-letters";
+
 #[event(fetch)]
 async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
+    if req.path().starts_with("/admin/") {
+        return admin(req, env).await;
+    }
+
     let headers = req.headers().into();
     // Basic auth check
     if let Ok(pass) = env.secret("SECRET_KEY") {
-        if let Err(response) =
+        if let Err(e) =
             shared_lib::auth::check_auth("admin".to_string(), pass.to_string(), &headers)
         {
-            return Response::try_from(response);
+            return error_response(e);
         }
     }
 
     match req.headers().get("User-Agent") {
-        Ok(Some(user_agent)) if user_agent.starts_with("Sonarr/") => {}
-        _ => return Response::error("Invalid User-Agent", 400),
+        Ok(Some(user_agent))
+            if user_agent.starts_with(Sonarr::USER_AGENT)
+                || user_agent.starts_with(Radarr::USER_AGENT)
+                || user_agent.starts_with(Lidarr::USER_AGENT) => {}
+        _ => return error_response(HookbufferError::UnsupportedUserAgent),
     };
 
     Router::new()
@@ -38,14 +60,322 @@ async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
                 stub.fetch_with_request(req).await
             },
         )
+        .on_async(
+            "/services/:team/:bot/:token",
+            async |req: Request, ctx: RouteContext<()>| {
+                let group_id = ctx.param("bot").unwrap();
+
+                let namespace = ctx.durable_object("HOOKBUFFER")?;
+                let stub = namespace.id_from_name(group_id)?.get_stub()?;
+                stub.fetch_with_request(req).await
+            },
+        )
+        .on_async(
+            "/_matrix/client/:version/rooms/:room_id/send/m.room.message",
+            async |req: Request, ctx: RouteContext<()>| {
+                let group_id = ctx.param("room_id").unwrap();
+
+                let namespace = ctx.durable_object("HOOKBUFFER")?;
+                let stub = namespace.id_from_name(group_id)?.get_stub()?;
+                stub.fetch_with_request(req).await
+            },
+        )
+        .on_async(
+            "/ntfy/:topic",
+            async |req: Request, ctx: RouteContext<()>| {
+                let group_id = ctx.param("topic").unwrap();
+
+                let namespace = ctx.durable_object("HOOKBUFFER")?;
+                let stub = namespace.id_from_name(group_id)?.get_stub()?;
+                stub.fetch_with_request(req).await
+            },
+        )
+        .on_async(
+            "/gotify/:token",
+            async |req: Request, ctx: RouteContext<()>| {
+                let group_id = ctx.param("token").unwrap();
+
+                let namespace = ctx.durable_object("HOOKBUFFER")?;
+                let stub = namespace.id_from_name(group_id)?.get_stub()?;
+                stub.fetch_with_request(req).await
+            },
+        )
+        .on_async(
+            "/json/*path",
+            async |req: Request, ctx: RouteContext<()>| {
+                let group_id = ctx.param("path").unwrap();
+
+                let namespace = ctx.durable_object("HOOKBUFFER")?;
+                let stub = namespace.id_from_name(group_id)?.get_stub()?;
+                stub.fetch_with_request(req).await
+            },
+        )
+        .on_async(
+            "/:bot/:chat_id",
+            async |req: Request, ctx: RouteContext<()>| {
+                // only Telegram style bot<token>/<chat id> paths are accepted here
+                let bot = ctx.param("bot").unwrap();
+                if !bot.starts_with("bot") || !bot.contains(':') {
+                    return Response::error("Not Found", 404);
+                }
+                // one queue per bot and chat, chat ids are only unique for a single bot
+                // the bot id before the : is used so the token isn't part of the name
+                let bot_id = bot.split(':').next().unwrap_or(bot);
+                let group_id = format!("{}/{}", bot_id, ctx.param("chat_id").unwrap());
+
+                let namespace = ctx.durable_object("HOOKBUFFER")?;
+                let stub = namespace.id_from_name(&group_id)?.get_stub()?;
+                stub.fetch_with_request(req).await
+            },
+        )
         .run(req, env)
         .await
 }
 
-fn hash_group_key(s: &SonarrGroupKey) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    s.hash(&mut hasher);
-    hasher.finish()
+// a rejected webhook as the same {"error", "message"} JSON the standalone server sends
+fn error_response(e: HookbufferError) -> Result<Response> {
+    Ok(Response::from_json(&serde_json::json!({
+        "error": e.code(),
+        "message": e.to_string(),
+    }))?
+    .with_status(e.status().as_u16()))
+}
+
+// the same admin API as the standalone server, forwarded to the durable objects holding each queue
+async fn admin(req: Request, env: Env) -> Result<Response> {
+    let pass = match env.secret("SECRET_KEY") {
+        Ok(pass) => pass.to_string(),
+        Err(_) => return error_response(HookbufferError::AdminDisabled),
+    };
+    if let Err(e) = shared_lib::auth::check_auth("admin".to_string(), pass, &req.headers().into()) {
+        return error_response(e);
+    }
+
+    let url = req.url()?;
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let namespace = env.durable_object("HOOKBUFFER")?;
+    let registry = namespace.id_from_name(REGISTRY)?.get_stub()?;
+
+    // (path, durable object id) of every queue with something in it
+    let routes: Vec<(String, String)> =
+        serde_json::from_value(call(&registry, Method::Get, "/admin/registry/routes", &[]).await?)?;
+    let path = param("path");
+    let matching = routes
+        .iter()
+        .filter(|(route, _)| {
+            // the path can be given as it was posted to or as it's listed, with its token redacted
+            path.as_deref().is_none_or(|path| {
+                let shown = Destination::from_path(route).redact_path(route);
+                [route.as_str(), shown.as_str()]
+                    .iter()
+                    .any(|route| path.trim_matches('/') == route.trim_matches('/'))
+            })
+        })
+        .collect::<Vec<_>>();
+    let nothing_queued = |path: &str| {
+        error_response(HookbufferError::NotFound(format!(
+            "Nothing is queued for /{}",
+            path.trim_matches('/')
+        )))
+    };
+
+    match (req.method(), req.path().as_str()) {
+        (Method::Get, "/admin/queues") => {
+            let mut queues = Vec::new();
+            for (_, id) in &routes {
+                let stub = namespace.id_from_string(id)?.get_stub()?;
+                if let serde_json::Value::Array(status) =
+                    call(&stub, Method::Get, "/admin/queue", &[]).await?
+                {
+                    queues.extend(status);
+                }
+            }
+            let paused = call(&registry, Method::Get, "/admin/registry/paused", &[]).await?;
+            Response::from_json(&serde_json::json!({
+                "queues": queues,
+                "paused": paused,
+            }))
+        }
+        (Method::Post, action @ ("/admin/flush" | "/admin/drop")) => {
+            let key = if action == "/admin/flush" {
+                "flushed"
+            } else {
+                "dropped"
+            };
+            match path.as_deref() {
+                None if action == "/admin/drop" => {
+                    return error_response(HookbufferError::BadRequest(
+                        "Pass the queue to drop as ?path=<path>".to_string(),
+                    ))
+                }
+                Some(path) if matching.is_empty() => return nothing_queued(path),
+                _ => {}
+            }
+            let mut total = 0;
+            for (_, id) in matching {
+                let stub = namespace.id_from_string(id)?.get_stub()?;
+                total += call(&stub, Method::Post, action, &[]).await?[key]
+                    .as_u64()
+                    .unwrap_or_default();
+            }
+            Response::from_json(&serde_json::json!({ key: total }))
+        }
+        (Method::Post, action @ ("/admin/pause" | "/admin/resume")) => {
+            let destination = match param("destination") {
+                Some(destination) if Destination::NAMES.contains(&destination.as_str()) => {
+                    destination
+                }
+                Some(destination) => {
+                    return error_response(HookbufferError::BadRequest(format!(
+                        "Unknown destination {}, must be one of {}",
+                        destination,
+                        Destination::NAMES.join(", ")
+                    )))
+                }
+                None => {
+                    return error_response(HookbufferError::BadRequest(
+                        "Pass the destination as ?destination=<name>".to_string(),
+                    ))
+                }
+            };
+            let action = action.replace("/admin/", "/admin/registry/");
+            let paused = call(
+                &registry,
+                Method::Post,
+                &action,
+                &[("destination", &destination)],
+            )
+            .await?;
+            Response::from_json(&serde_json::json!({ "paused": paused }))
+        }
+        (Method::Get, "/admin/dead-letters") => {
+            let dead_letters =
+                call(&registry, Method::Get, "/admin/registry/dead-letters", &[]).await?;
+            let dead_letters = serde_json::from_value::<Vec<DeadLetter>>(dead_letters)?
+                .iter()
+                .map(DeadLetter::redacted)
+                .collect::<Vec<_>>();
+            Response::from_json(&serde_json::json!({ "dead_letters": dead_letters }))
+        }
+        (Method::Post, "/admin/dead-letters/retry") => {
+            let id = match dead_letter_id(param("id")) {
+                Ok(id) => id,
+                Err(e) => return error_response(e),
+            };
+            let taken = call(
+                &registry,
+                Method::Post,
+                "/admin/registry/dead-letters/take",
+                &[("id", &id)],
+            )
+            .await?;
+            let Some(letter) = serde_json::from_value::<Option<DeadLetter>>(taken)? else {
+                return error_response(unknown_dead_letter(&id));
+            };
+            // it goes back through the outbound queue, and ends up here again if it keeps failing
+            env.queue("outbound_messages")?.send(letter.webhook).await?;
+            Response::from_json(&serde_json::json!({ "queued": true }))
+        }
+        (Method::Post, "/admin/dead-letters/delete") => {
+            let id = match dead_letter_id(param("id")) {
+                Ok(id) => id,
+                Err(e) => return error_response(e),
+            };
+            let deleted = call(
+                &registry,
+                Method::Post,
+                "/admin/registry/dead-letters/delete",
+                &[("id", &id)],
+            )
+            .await?;
+            if deleted["deleted"].as_bool() != Some(true) {
+                return error_response(unknown_dead_letter(&id));
+            }
+            Response::from_json(&serde_json::json!({ "deleted": id.parse::<u64>().ok() }))
+        }
+        _ => Response::error("Not Found", 404),
+    }
+}
+
+fn dead_letter_id(id: Option<String>) -> std::result::Result<String, HookbufferError> {
+    match id {
+        Some(id) if id.parse::<u64>().is_ok() => Ok(id),
+        _ => Err(HookbufferError::BadRequest(
+            "Pass the dead letter as ?id=<id>".to_string(),
+        )),
+    }
+}
+
+fn unknown_dead_letter(id: &str) -> HookbufferError {
+    HookbufferError::NotFound(format!("There's no dead letter with id {}", id))
+}
+
+// send an internal admin request to a durable object and read the JSON it responds with
+async fn call(
+    stub: &Stub,
+    method: Method,
+    path: &str,
+    params: &[(&str, &str)],
+) -> Result<serde_json::Value> {
+    let url = Url::parse_with_params(&format!("https://hookbuffer{}", path), params)?;
+    let req = Request::new_with_init(url.as_str(), RequestInit::new().with_method(method))?;
+    stub.fetch_with_request(req).await?.json().await
+}
+
+// like call, but POSTing a JSON body
+async fn post_json<T: serde::Serialize>(
+    stub: &Stub,
+    path: &str,
+    body: &T,
+) -> Result<serde_json::Value> {
+    let body = serde_json::to_string(body)?;
+    let req = Request::new_with_init(
+        &format!("https://hookbuffer{}", path),
+        RequestInit::new()
+            .with_method(Method::Post)
+            .with_body(Some(JsValue::from(body))),
+    )?;
+    stub.fetch_with_request(req).await?.json().await
+}
+
+// zero padded so the registry lists them in order
+fn dead_letter_key(id: u64) -> String {
+    format!("dead:{:020}", id)
+}
+
+// the last failed send of a webhook, kept by the registry until the webhook is delivered
+// or the dead letter queue turns it into a dead letter
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Failure {
+    status: Option<u16>,
+    error: String,
+    // how many times the outbound queue has delivered the webhook to the consumer
+    attempts: u32,
+}
+
+// the webhook was delivered or went back in the queue as a new message, so its failure won't be needed
+// it's best effort since failing the batch would send the webhook again
+async fn forget_failure(registry: &Stub, key: &str) {
+    if let Err(e) = call(
+        registry,
+        Method::Post,
+        "/admin/registry/failures/take",
+        &[("key", key)],
+    )
+    .await
+    {
+        console_error!("Couldn't forget the failure of a webhook: {}", e);
+    }
+}
+
+// the same webhook has the same key in the outbound queue and the dead letter queue
+fn failure_key(webhook: &DiscordWebhook) -> String {
+    let body = serde_json::to_string(webhook).unwrap_or_default();
+    format!("failure:{}", stable_hash(&body))
 }
 
 #[durable_object]
@@ -61,29 +391,438 @@ impl DurableObject for ChannelQueue {
     }
 
     async fn fetch(&mut self, req: Request) -> Result<Response> {
-        self.state.storage().set_alarm(15 * 1000).await?;
+        // only the worker can reach a durable object, so these have already been authorized
+        if req.path().starts_with("/admin/") {
+            return self.admin(&req).await;
+        }
+
+        let user_agent = req.headers().get("User-Agent")?.unwrap_or_default();
+
+        if user_agent.starts_with(Radarr::USER_AGENT) {
+            self.accept::<Radarr>(&req).await
+        } else if user_agent.starts_with(Lidarr::USER_AGENT) {
+            self.accept::<Lidarr>(&req).await
+        } else {
+            self.accept::<Sonarr>(&req).await
+        }
+    }
+
+    async fn alarm(&mut self) -> Result<Response> {
+        let path: String = self.state.storage().get("url").await?;
+        let (destination, _) = self.target(&path);
+        // paused destinations keep buffering until they're resumed
+        if self.is_paused(&destination).await? {
+            console_log!(
+                "Sending to {} is paused, holding the queue",
+                destination.name()
+            );
+            self.state.storage().set_alarm(PAUSE_RECHECK).await?;
+            return Response::from_json(&serde_json::json!({
+                "success": true,
+                "paused": true
+            }));
+        }
+
+        self.flush().await?;
+
+        Response::from_json(&serde_json::json!({
+            "success": true,
+        }))
+    }
+}
+
+impl ChannelQueue {
+    // requests from the worker's admin API, and the registry's own requests when this is the registry
+    async fn admin(&self, req: &Request) -> Result<Response> {
+        let url = req.url()?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+        };
+
+        match (req.method(), req.path().as_str()) {
+            (Method::Get, "/admin/queue") => Response::from_json(&self.status().await?),
+            (Method::Post, "/admin/flush") => {
+                let flushed = self.flush().await?;
+                self.state.storage().delete_alarm().await?;
+                Response::from_json(&serde_json::json!({ "flushed": flushed }))
+            }
+            (Method::Post, "/admin/drop") => {
+                let dropped = self.drop_stored::<Sonarr>().await?
+                    + self.drop_stored::<Radarr>().await?
+                    + self.drop_stored::<Lidarr>().await?;
+                self.state.storage().delete("first_queued_at").await?;
+                self.state.storage().delete_alarm().await?;
+                self.unregister().await?;
+                Response::from_json(&serde_json::json!({ "dropped": dropped }))
+            }
+            (Method::Get, "/admin/registry/routes") => {
+                let routes = self
+                    .state
+                    .storage()
+                    .list_with_options(ListOptions::new().prefix("route:"))
+                    .await?
+                    .entries()
+                    .into_iter()
+                    .filter_map(|entry| {
+                        serde_wasm_bindgen::from_value::<(String, String)>(entry.ok()?).ok()
+                    })
+                    .map(|(key, id)| (key.trim_start_matches("route:").to_string(), id))
+                    .collect::<Vec<_>>();
+                Response::from_json(&routes)
+            }
+            (Method::Post, "/admin/registry/register") => {
+                let (Some(path), Some(id)) = (param("path"), param("id")) else {
+                    return Response::error("Missing path or id", 400);
+                };
+                self.state
+                    .storage()
+                    .put(&format!("route:{}", path), id)
+                    .await?;
+                Response::from_json(&serde_json::json!({ "success": true }))
+            }
+            (Method::Post, "/admin/registry/unregister") => {
+                if let Some(path) = param("path") {
+                    self.state
+                        .storage()
+                        .delete(&format!("route:{}", path))
+                        .await?;
+                }
+                Response::from_json(&serde_json::json!({ "success": true }))
+            }
+            (Method::Get, "/admin/registry/paused") => {
+                Response::from_json(&self.stored_pauses().await)
+            }
+            (Method::Post, action @ ("/admin/registry/pause" | "/admin/registry/resume")) => {
+                let Some(destination) = param("destination") else {
+                    return Response::error("Missing destination", 400);
+                };
+                let mut paused = self.stored_pauses().await;
+                paused.retain(|paused| *paused != destination);
+                if action == "/admin/registry/pause" {
+                    console_log!("Pausing sending to {}", destination);
+                    paused.push(destination);
+                    paused.sort();
+                } else {
+                    console_log!("Resuming sending to {}", destination);
+                }
+                self.state.storage().put("paused", &paused).await?;
+                Response::from_json(&paused)
+            }
+            (Method::Get, "/admin/registry/dead-letters") => {
+                Response::from_json(&self.stored_dead_letters().await?)
+            }
+            (Method::Post, "/admin/registry/dead-letters/add") => {
+                let mut letter: DeadLetter = req.clone()?.json().await?;
+                let id = self
+                    .state
+                    .storage()
+                    .get::<u64>("dead_letter_id")
+                    .await
+                    .unwrap_or_default()
+                    + 1;
+                letter.id = id;
+                console_warn!(
+                    "Keeping {} webhook for {} as dead letter {}: {}",
+                    letter.webhook.event_type,
+                    letter.webhook.destination.name(),
+                    id,
+                    letter.error
+                );
+                self.state.storage().put("dead_letter_id", id).await?;
+                self.state
+                    .storage()
+                    .put(&dead_letter_key(id), serde_json::to_string(&letter)?)
+                    .await?;
+                Response::from_json(&letter)
+            }
+            (Method::Post, "/admin/registry/failures/put") => {
+                let Some(key) = param("key") else {
+                    return Response::error("Missing key", 400);
+                };
+                let failure: Failure = req.clone()?.json().await?;
+                self.state
+                    .storage()
+                    .put(&key, serde_json::to_string(&failure)?)
+                    .await?;
+                Response::from_json(&serde_json::json!({ "success": true }))
+            }
+            (Method::Post, "/admin/registry/failures/take") => {
+                let Some(key) = param("key") else {
+                    return Response::error("Missing key", 400);
+                };
+                let failure = self
+                    .state
+                    .storage()
+                    .get::<String>(&key)
+                    .await
+                    .ok()
+                    .and_then(|failure| serde_json::from_str::<Failure>(&failure).ok());
+                if failure.is_some() {
+                    self.state.storage().delete(&key).await?;
+                }
+                Response::from_json(&failure)
+            }
+            (
+                Method::Post,
+                action @ ("/admin/registry/dead-letters/take"
+                | "/admin/registry/dead-letters/delete"),
+            ) => {
+                let Some(id) = param("id").and_then(|id| id.parse::<u64>().ok()) else {
+                    return Response::error("Missing id", 400);
+                };
+                let key = dead_letter_key(id);
+                let letter = self
+                    .state
+                    .storage()
+                    .get::<String>(&key)
+                    .await
+                    .ok()
+                    .and_then(|letter| serde_json::from_str::<DeadLetter>(&letter).ok());
+                if letter.is_some() {
+                    self.state.storage().delete(&key).await?;
+                }
+                if action == "/admin/registry/dead-letters/take" {
+                    Response::from_json(&letter)
+                } else {
+                    Response::from_json(&serde_json::json!({ "deleted": letter.is_some() }))
+                }
+            }
+            _ => Response::error("Not Found", 404),
+        }
+    }
+
+    // destinations paused through the admin API, only set on the registry
+    async fn stored_pauses(&self) -> Vec<String> {
+        self.state
+            .storage()
+            .get::<Vec<String>>("paused")
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn is_paused(&self, destination: &Destination) -> Result<bool> {
+        let paused = call(
+            &self.registry()?,
+            Method::Get,
+            "/admin/registry/paused",
+            &[],
+        )
+        .await?;
+        Ok(paused
+            .as_array()
+            .is_some_and(|paused| paused.iter().any(|paused| paused == destination.name())))
+    }
+
+    // dead letters kept by the registry, oldest first
+    async fn stored_dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        Ok(self
+            .state
+            .storage()
+            .list_with_options(ListOptions::new().prefix("dead:"))
+            .await?
+            .entries()
+            .into_iter()
+            .filter_map(|entry| {
+                let (_, letter) =
+                    serde_wasm_bindgen::from_value::<(String, String)>(entry.ok()?).ok()?;
+                serde_json::from_str(&letter).ok()
+            })
+            .collect())
+    }
+
+    fn registry(&self) -> Result<Stub> {
+        self.env
+            .durable_object("HOOKBUFFER")?
+            .id_from_name(REGISTRY)?
+            .get_stub()
+    }
+
+    // let the registry know this queue has something in it
+    async fn register(&self, path: &str) -> Result<()> {
+        let id = self.state.id().to_string();
+        call(
+            &self.registry()?,
+            Method::Post,
+            "/admin/registry/register",
+            &[("path", path), ("id", &id)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn unregister(&self) -> Result<()> {
+        if let Ok(path) = self.state.storage().get::<String>("url").await {
+            call(
+                &self.registry()?,
+                Method::Post,
+                "/admin/registry/unregister",
+                &[("path", &path)],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // send every stored group to the outbound queue, returns how many events were sent
+    async fn flush(&self) -> Result<usize> {
+        let outbound_queue = self.env.queue("outbound_messages")?;
+
+        self.state.storage().delete("first_queued_at").await?;
+
+        let path: String = self.state.storage().get("url").await?;
+        let (destination, url) = self.target(&path);
+        let url = &url;
+        let flushed_at = Date::now().as_millis() as u128;
+        let style = self.render_style();
+        let templates = self.templates();
+        let templates = templates.as_ref();
+
+        let mut flushed = 0;
+        for (group_key, webhook, count) in self
+            .take_groups::<Sonarr>(url, &destination, flushed_at, style, templates)
+            .await?
+            .into_iter()
+            .chain(
+                self.take_groups::<Radarr>(url, &destination, flushed_at, style, templates)
+                    .await?,
+            )
+            .chain(
+                self.take_groups::<Lidarr>(url, &destination, flushed_at, style, templates)
+                    .await?,
+            )
+        {
+            self.state.storage().delete(&group_key).await?;
+            outbound_queue.send(webhook).await?;
+            flushed += count;
+        }
+        self.unregister().await?;
+
+        Ok(flushed)
+    }
+
+    // what's waiting in this queue, in the same shape as the standalone server's admin API
+    async fn status(&self) -> Result<Vec<serde_json::Value>> {
+        let Ok(path) = self.state.storage().get::<String>("url").await else {
+            return Ok(Vec::new());
+        };
+        let (destination, _) = self.target(&path);
+        let flush_in_secs = self.state.storage().get_alarm().await?.map_or(0.0, |at| {
+            (at - Date::now().as_millis() as i64).max(0) as f64 / 1000.0
+        });
+        let paused = self.is_paused(&destination).await?;
+
+        let mut queues = Vec::new();
+        for (source, groups, queue_length) in [
+            self.group_ids::<Sonarr>().await?,
+            self.group_ids::<Radarr>().await?,
+            self.group_ids::<Lidarr>().await?,
+        ] {
+            if queue_length == 0 {
+                continue;
+            }
+            queues.push(serde_json::json!({
+                "source": source,
+                "path": destination.redact_path(path.trim_start_matches('/')),
+                "route": null,
+                "destination": destination.name(),
+                "queue_length": queue_length,
+                "groups": groups,
+                "flush_in_secs": flush_in_secs,
+                "paused": paused,
+            }));
+        }
+        Ok(queues)
+    }
+
+    // the source's name, the ids of its stored groups and how many events they hold
+    async fn group_ids<S: Source>(&self) -> Result<(&'static str, Vec<String>, usize)> {
+        let stored = self.stored::<S>().await?;
+        let queue_length = stored.iter().map(|(_, items)| items.len()).sum();
+        let groups = stored
+            .iter()
+            .filter_map(|(_, items)| items.first())
+            .map(|event| group_id::<S>(&S::group_key(event)))
+            .collect();
+        Ok((S::NAME, groups, queue_length))
+    }
+
+    // delete the source's stored groups without sending them, returns how many events they held
+    async fn drop_stored<S: Source>(&self) -> Result<usize> {
+        let mut dropped = 0;
+        for (group_key, items) in self.stored::<S>().await? {
+            self.state.storage().delete(&group_key).await?;
+            dropped += items.len();
+        }
+        Ok(dropped)
+    }
 
-        let sonarr_event: SonarrRequestBody = {
-            let mut req = req.clone()?;
-            req.json().await?
+    // handle a webhook from the given source, tests are acknowledged, alerts are sent right away
+    // and everything else is queued until the alarm goes off
+    async fn accept<S: Source>(&self, req: &Request) -> Result<Response> {
+        let body = req.clone()?.text().await?;
+        let body: serde_json::Value = match serde_json::from_str(&body) {
+            Ok(body) => body,
+            Err(e) => return error_response(HookbufferError::InvalidJson(e.to_string())),
         };
-        let group_key = {
-            let key: SonarrGroupKey = (&sonarr_event).into();
-            format!("groupkey-{}", hash_group_key(&key))
+        let event = match S::parse(body) {
+            Ok(event) => event,
+            Err(e) => {
+                console_warn!("[{}] Rejected webhook: {}", S::NAME, e);
+                return error_response(e.into());
+            }
         };
 
-        let group_items = {
-            let mut items = self
-                .state
-                .storage()
-                .get::<Vec<SonarrRequestBody>>(&group_key)
-                .await
-                .unwrap_or_default();
-            items.push(sonarr_event);
-            self.state.storage().put(&group_key, &items).await?;
-            self.state.storage().put("url", req.path()).await?;
-            items.len()
+        match S::delivery(&event) {
+            Delivery::Test => {
+                return Response::from_json(&serde_json::json!({
+                    "success": true,
+                    "test": true
+                }))
+            }
+            Delivery::Immediate
+                if !self.is_paused(&Destination::from_path(&req.path())).await? =>
+            {
+                let (destination, url) = self.target(&req.path());
+                let templates = self.templates();
+                let webhook = build_webhook::<S>(
+                    url,
+                    &destination,
+                    vec![event],
+                    Date::now().as_millis() as u128,
+                    self.render_style(),
+                    templates.as_ref(),
+                );
+                self.env.queue("outbound_messages")?.send(webhook).await?;
+                return Response::from_json(&serde_json::json!({
+                    "success": true,
+                    "queue_length": 0
+                }));
+            }
+            // alerts for a paused destination wait with everything else
+            Delivery::Immediate | Delivery::Buffered => {}
+        }
+
+        // the debounce window restarts with every webhook, but never past the max wait after the first one
+        let now = Date::now().as_millis();
+        let first_queued_at = match self.state.storage().get::<u64>("first_queued_at").await {
+            Ok(first_queued_at) => first_queued_at,
+            Err(_) => {
+                self.state.storage().put("first_queued_at", now).await?;
+                self.register(&req.path()).await?;
+                now
+            }
         };
+        let delay = flush_delay(
+            self.duration_var("DEBOUNCE_SECS", DEFAULT_DEBOUNCE),
+            self.duration_var("MAX_WAIT_SECS", DEFAULT_MAX_WAIT),
+            Duration::from_millis(now.saturating_sub(first_queued_at)),
+        );
+        self.state.storage().set_alarm(delay).await?;
+
+        let group_items = self.push_event::<S>(event).await?;
+        self.state.storage().put("url", req.path()).await?;
 
         console_log!("Added item to channel queue, group length: {}", group_items);
 
@@ -93,10 +832,117 @@ impl DurableObject for ChannelQueue {
         }))
     }
 
-    async fn alarm(&mut self) -> Result<Response> {
-        let outbound_queue = self.env.queue("outbound_messages")?;
+    // where webhooks for the given path are sent
+    fn target(&self, path: &str) -> (Destination, String) {
+        let mut destination = Destination::from_path(path);
+        let default_base_url = destination.default_base_url();
+        let base_url = match &mut destination {
+            Destination::Gotify { .. } => self
+                .env
+                .var("GOTIFY_URL")
+                .map(|var| format!("{}/", var.to_string().trim_end_matches('/')))
+                .unwrap_or_else(|_| default_base_url.to_string()),
+            Destination::Json => self
+                .env
+                .var("JSON_URL")
+                .map(|var| format!("{}/", var.to_string().trim_end_matches('/')))
+                .unwrap_or_else(|_| default_base_url.to_string()),
+            Destination::Matrix { access_token, .. } => {
+                *access_token = self
+                    .env
+                    .secret("MATRIX_ACCESS_TOKEN")
+                    .ok()
+                    .map(|secret| secret.to_string());
+                self.env
+                    .var("MATRIX_HOMESERVER_URL")
+                    .map(|var| format!("{}/", var.to_string().trim_end_matches('/')))
+                    .unwrap_or_else(|_| default_base_url.to_string())
+            }
+            _ => default_base_url.to_string(),
+        };
+        let url = destination.webhook_url(&base_url, path);
+        (destination, url)
+    }
+
+    fn render_style(&self) -> RenderStyle {
+        self.env
+            .var("RENDER_STYLE")
+            .ok()
+            .and_then(|var| var.to_string().parse::<RenderStyle>().ok())
+            .unwrap_or_default()
+    }
+
+    // a JSON object in the same shape as a standalone route's templates
+    fn templates(&self) -> Option<Templates> {
+        self.env
+            .var("TEMPLATES")
+            .ok()
+            .and_then(|var| serde_json::from_str::<Templates>(&var.to_string()).ok())
+    }
+
+    // read a number of seconds from a var, falling back to the default if it's missing or invalid
+    fn duration_var(&self, name: &str, default: Duration) -> Duration {
+        self.env
+            .var(name)
+            .ok()
+            .and_then(|var| var.to_string().parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map_or(default, Duration::from_secs)
+    }
+
+    // append the event to the stored list for its group
+    // returns the new group length
+    async fn push_event<S: Source>(&self, event: S::Event) -> Result<usize> {
+        let group_key = format!(
+            "{}-groupkey-{}",
+            S::NAME,
+            stable_hash(&S::group_key(&event))
+        );
+
+        let mut items = self
+            .state
+            .storage()
+            .get::<Vec<S::Event>>(&group_key)
+            .await
+            .unwrap_or_default();
+        items.push(event);
+        self.state.storage().put(&group_key, &items).await?;
+        Ok(items.len())
+    }
+
+    // read every stored group for the given source and render each one into a webhook
+    // returns the storage key, webhook and number of events of each group
+    async fn take_groups<S: Source>(
+        &self,
+        url: &str,
+        destination: &Destination,
+        flushed_at: u128,
+        style: RenderStyle,
+        templates: Option<&Templates>,
+    ) -> Result<Vec<(String, DiscordWebhook, usize)>> {
+        Ok(self
+            .stored::<S>()
+            .await?
+            .into_iter()
+            .map(|(group_key, group_items)| {
+                let count = group_items.len();
+                let webhook = build_webhook::<S>(
+                    url.to_string(),
+                    destination,
+                    group_items,
+                    flushed_at,
+                    style,
+                    templates,
+                );
+                (group_key, webhook, count)
+            })
+            .collect())
+    }
 
-        let list_options = ListOptions::new().prefix("groupkey-");
+    // every stored group for the given source, by storage key
+    async fn stored<S: Source>(&self) -> Result<Vec<(String, Vec<S::Event>)>> {
+        let prefix = format!("{}-groupkey-", S::NAME);
+        let list_options = ListOptions::new().prefix(&prefix);
         let storage_map = self
             .state
             .storage()
@@ -104,51 +950,156 @@ impl DurableObject for ChannelQueue {
             .await?
             .entries();
 
-        let url = &{
-            let path: String = self.state.storage().get("url").await?;
-            format!("https://discord.com{}", path)
-        };
-
+        let mut groups = Vec::new();
         for entry in storage_map {
             let (group_key, group_items) = entry
                 .and_then(|val| {
                     if val.is_undefined() {
                         Err(JsValue::from("No such value in storage."))
                     } else {
-                        serde_wasm_bindgen::from_value::<(String, Vec<SonarrRequestBody>)>(val)
+                        serde_wasm_bindgen::from_value::<(String, Vec<S::Event>)>(val)
                             .map_err(|e| JsValue::from(e.to_string()))
                     }
                 })
                 .map_err(Error::from)?;
 
-            let webhook: DiscordWebhookBody = group_items.into();
-            self.state.storage().delete(&group_key).await?;
-            outbound_queue
-                .send(DiscordWebhook::new(url.to_string(), webhook))
-                .await?;
+            groups.push((group_key, group_items));
         }
 
-        Response::from_json(&serde_json::json!({
-            "success": true,
-        }))
+        Ok(groups)
     }
 }
 
-#[event(queue)]
+// #[event(queue)] hides how many times a message has been delivered,
+// so the export it would generate is written out here to read that from the raw messages
+mod queue_export {
+    use worker::wasm_bindgen::prelude::*;
+    use worker::{js_sys, wasm_bindgen, wasm_bindgen_futures, worker_sys};
+
+    #[wasm_bindgen]
+    pub async fn queue(
+        event: worker_sys::MessageBatch,
+        env: worker::Env,
+        ctx: worker_sys::Context,
+    ) {
+        // in the same order as MessageBatch::messages
+        let attempts = event
+            .messages()
+            .map(|messages| {
+                messages
+                    .iter()
+                    .map(|message| {
+                        js_sys::Reflect::get(&message, &JsValue::from_str("attempts"))
+                            .ok()
+                            .and_then(|attempts| attempts.as_f64())
+                            .map_or(1, |attempts| attempts as u32)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let batch = worker::MessageBatch::from(event);
+        let ctx = worker::Context::new(ctx);
+        if let Err(e) = super::consume_webhook_queue(batch, attempts, env, ctx).await {
+            worker::console_log!("{}", &e);
+            panic!("{}", e);
+        }
+    }
+}
+
+// attempts are how many times each message has been delivered, including this time
 pub async fn consume_webhook_queue(
     message_batch: MessageBatch<DiscordWebhook>,
-    _env: Env,
+    attempts: Vec<u32>,
+    env: Env,
     _ctx: Context,
 ) -> Result<()> {
     let messages: Vec<Message<DiscordWebhook>> = message_batch.messages()?;
+    let registry = env
+        .durable_object("HOOKBUFFER")?
+        .id_from_name(REGISTRY)?
+        .get_stub()?;
 
-    for message in messages {
+    // webhooks the outbound queue ran out of retries for are kept by the registry for the admin API
+    if message_batch.queue() == DEAD_LETTER_QUEUE {
+        for message in messages {
+            let webhook = message.body().clone();
+            let failure = call(
+                &registry,
+                Method::Post,
+                "/admin/registry/failures/take",
+                &[("key", &failure_key(&webhook))],
+            )
+            .await
+            .ok()
+            .and_then(|failure| serde_json::from_value::<Failure>(failure).ok())
+            // the failure wasn't recorded, most likely because the registry couldn't be reached
+            .unwrap_or(Failure {
+                status: None,
+                error: "Gave up after the outbound queue ran out of retries".to_string(),
+                attempts: 0,
+            });
+            let letter = DeadLetter {
+                // the registry picks the id
+                id: 0,
+                failed_at: Date::now().as_millis(),
+                webhook,
+                status: failure.status,
+                error: failure.error,
+                attempts: failure.attempts,
+            };
+            post_json(&registry, "/admin/registry/dead-letters/add", &letter).await?;
+            message.ack();
+        }
+        return Ok(());
+    }
+
+    // failed sends are retried by the queue, see max_retries in wrangler.toml, retrying them here as well would multiply the attempts
+    RetryPolicy {
+        max_attempts: 1,
+        max_retry_time: Duration::ZERO,
+    }
+    .install();
+    let attempts = attempts.into_iter().chain(std::iter::repeat(1));
+    for (message, attempts) in messages.into_iter().zip(attempts) {
         let webhook = message.body().clone();
-        match shared_lib::send::send_post_request(webhook.url, webhook.body).await {
-            Ok(_) => message.ack(),
-            Err(_) => message.retry(),
+        let key = failure_key(&webhook);
+        match shared_lib::send::send_webhook(webhook.clone()).await {
+            Ok(_) => {
+                // an earlier attempt left a failure behind
+                if attempts > 1 {
+                    forget_failure(&registry, &key).await;
+                }
+                message.ack();
+            }
+            Err(e) if e.sent_parts > webhook.sent_parts => {
+                // retrying the message would send the parts that were delivered again, so only the rest goes back in the queue
+                if attempts > 1 {
+                    forget_failure(&registry, &key).await;
+                }
+                let rest = DiscordWebhook {
+                    sent_parts: e.sent_parts,
+                    ..webhook
+                };
+                env.queue("outbound_messages")?.send(rest).await?;
+                message.ack();
+            }
+            Err(e) => {
+                // remembered for the dead letter queue in case this was the last retry
+                let failure = Failure {
+                    status: e.status.map(|status| status.as_u16()),
+                    error: e.error,
+                    attempts,
+                };
+                let path = format!("/admin/registry/failures/put?key={}", key);
+                if let Err(e) = post_json(&registry, &path, &failure).await {
+                    console_error!("Couldn't record the failure of a webhook: {}", e);
+                }
+                message.retry();
+            }
         };
-        Delay::from(Duration::from_secs(1)).await;
+        // space out sends for destinations that haven't told the rate limiter about their limits yet
+        Delay::from(SEND_SPACING).await;
     }
 
     Ok(())
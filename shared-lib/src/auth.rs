@@ -1,18 +1,15 @@
-use axum::{
-    body::Body,
-    http::{header::HeaderMap, Response, StatusCode},
-    response::IntoResponse,
-    Json,
-};
+use axum::http::header::HeaderMap;
 use base64::{engine::general_purpose, Engine as _};
 
+use crate::error::HookbufferError;
+
 pub fn check_auth(
     user_value: String,
     pass_value: String,
     headers: &HeaderMap,
-) -> Result<(), Response<Body>> {
-    let unauthorized = |msg| (StatusCode::UNAUTHORIZED, Json(msg)).into_response();
-    let bad_request = |msg| (StatusCode::BAD_REQUEST, Json(msg)).into_response();
+) -> Result<(), HookbufferError> {
+    let unauthorized = |msg: &str| HookbufferError::Unauthorized(msg.to_string());
+    let bad_request = |msg: &str| HookbufferError::InvalidAuthorization(msg.to_string());
 
     let auth_header = headers
         .get("Authorization")
@@ -27,7 +24,7 @@ pub fn check_auth(
         .decode(auth)
         .map_err(|_| bad_request("Invalid Authorization header: couldn't decode base64"))?;
     let auth = String::from_utf8(decoded).map_err(|_| {
-        bad_request("Invalid Authorization header: couldn't convert dec/* o */ded utf8 to string")
+        bad_request("Invalid Authorization header: couldn't convert decoded utf8 to string")
     })?;
 
     let mut auth_parts = auth.splitn(2, ':');
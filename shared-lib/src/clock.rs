@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+// milliseconds since the epoch, std's clocks aren't available in workers
+pub fn now_ms() -> u64 {
+    #[cfg(all(feature = "worker", target_arch = "wasm32"))]
+    return worker::Date::now().as_millis();
+    #[cfg(not(all(feature = "worker", target_arch = "wasm32")))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+}
+
+// wait without holding up anything else, workers have no tokio timer so they wait on the runtime's instead
+pub async fn sleep(duration: Duration) {
+    #[cfg(all(feature = "worker", target_arch = "wasm32"))]
+    worker::Delay::from(duration).await;
+    #[cfg(not(all(feature = "worker", target_arch = "wasm32")))]
+    tokio::time::sleep(duration).await;
+}
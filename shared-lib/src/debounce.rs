@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+// how long to wait after the last webhook before sending
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(15);
+// how long to wait after the first webhook before sending no matter how many more arrive
+pub const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(300);
+
+// time left before a queue should flush, the debounce window restarts with every webhook
+// but the queue is never held longer than max_wait after its first webhook
+pub fn flush_delay(debounce: Duration, max_wait: Duration, since_first: Duration) -> Duration {
+    debounce.min(max_wait.saturating_sub(since_first))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_at_max_wait() {
+        let debounce = Duration::from_secs(15);
+        let max_wait = Duration::from_secs(60);
+
+        assert_eq!(flush_delay(debounce, max_wait, Duration::ZERO), debounce);
+        assert_eq!(
+            flush_delay(debounce, max_wait, Duration::from_secs(50)),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            flush_delay(debounce, max_wait, Duration::from_secs(90)),
+            Duration::ZERO
+        );
+    }
+}
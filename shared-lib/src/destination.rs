@@ -0,0 +1,375 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// per destination overrides for push services, keyed by event type name (Grab, Download, Upgrade, ...)
+// anything not listed falls back to the service's defaults
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct PushOptions {
+    #[serde(default)]
+    pub priorities: HashMap<String, u8>,
+    #[serde(default)]
+    pub tags: HashMap<String, Vec<String>>,
+}
+
+// where a buffered group ends up, every destination is rendered from the same DiscordWebhookBody
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Destination {
+    #[default]
+    Discord,
+    Slack,
+    Telegram {
+        chat_id: String,
+    },
+    Matrix {
+        room_id: String,
+        // not part of the inbound path, filled in from the environment before sending
+        #[serde(default)]
+        access_token: Option<String>,
+    },
+    Ntfy {
+        topic: String,
+        #[serde(default)]
+        options: PushOptions,
+    },
+    Gotify {
+        // application token, sent as a bearer token
+        token: String,
+        #[serde(default)]
+        options: PushOptions,
+    },
+    // the raw batch as a BatchDocument, for automation rather than people
+    Json,
+}
+
+impl Destination {
+    // figure out the destination from the shape of the inbound path
+    // Slack incoming webhooks look like services/T000/B000/XXXX
+    // Telegram paths look like bot<token>/<chat id>, mirroring the Bot API's own URLs
+    // Matrix paths mirror the client-server API: _matrix/client/v3/rooms/<room id>/send/m.room.message
+    // ntfy paths look like ntfy/<topic> and Gotify paths look like gotify/<application token>
+    // JSON paths look like json/<path on the receiving server>
+    // everything else is treated as Discord
+    pub fn from_path(path: &str) -> Self {
+        let segments = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+        match segments.as_slice() {
+            ["services", team, bot, _] if team.starts_with('T') && bot.starts_with('B') => {
+                Destination::Slack
+            }
+            [bot, chat_id] if bot.starts_with("bot") && bot.contains(':') => {
+                Destination::Telegram {
+                    chat_id: chat_id.to_string(),
+                }
+            }
+            ["_matrix", "client", _, "rooms", room_id, "send", "m.room.message"] => {
+                Destination::Matrix {
+                    room_id: decode_path_segment(room_id),
+                    access_token: None,
+                }
+            }
+            ["ntfy", topic] => Destination::Ntfy {
+                topic: topic.to_string(),
+                options: PushOptions::default(),
+            },
+            ["gotify", token] => Destination::Gotify {
+                token: token.to_string(),
+                options: PushOptions::default(),
+            },
+            ["json", ..] => Destination::Json,
+            _ => Destination::Discord,
+        }
+    }
+
+    // every name() a destination can have
+    pub const NAMES: [&'static str; 7] = [
+        "discord", "slack", "telegram", "matrix", "ntfy", "gotify", "json",
+    ];
+
+    // short lowercase name, matching how the destination is written in config files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Destination::Discord => "discord",
+            Destination::Slack => "slack",
+            Destination::Telegram { .. } => "telegram",
+            Destination::Matrix { .. } => "matrix",
+            Destination::Ntfy { .. } => "ntfy",
+            Destination::Gotify { .. } => "gotify",
+            Destination::Json => "json",
+        }
+    }
+
+    pub fn default_base_url(&self) -> &'static str {
+        match self {
+            Destination::Discord => "https://discord.com/",
+            Destination::Slack => "https://hooks.slack.com/",
+            Destination::Telegram { .. } => "https://api.telegram.org/",
+            Destination::Matrix { .. } => "https://matrix-client.matrix.org/",
+            Destination::Ntfy { .. } => "https://ntfy.sh/",
+            // gotify is always self hosted so there's no useful default
+            Destination::Gotify { .. } | Destination::Json => "http://localhost/",
+        }
+    }
+
+    // build the URL the rendered webhook gets sent to from the destination's base URL and the inbound path
+    pub fn webhook_url(&self, base_url: &str, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        match self {
+            Destination::Telegram { .. } => {
+                let bot = path.split('/').next().unwrap_or_default();
+                format!("{}{}/sendMessage", base_url, bot)
+            }
+            // the transaction id is appended when sending, see send::send_webhook
+            Destination::Matrix { room_id, .. } => format!(
+                "{}_matrix/client/v3/rooms/{}/send/m.room.message",
+                base_url,
+                encode_path_segment(room_id)
+            ),
+            // ntfy takes JSON messages on its root with the topic in the body
+            Destination::Ntfy { .. } => base_url.to_string(),
+            Destination::Gotify { .. } => format!("{}message", base_url),
+            Destination::Json => format!(
+                "{}{}",
+                base_url,
+                path.strip_prefix("json/").unwrap_or_default()
+            ),
+            _ => format!("{}{}", base_url, path),
+        }
+    }
+
+    // the destination with its secrets replaced, for showing it through the admin API
+    pub fn redacted(&self) -> Self {
+        match self {
+            Destination::Matrix {
+                room_id,
+                access_token,
+            } => Destination::Matrix {
+                room_id: room_id.clone(),
+                access_token: access_token.as_ref().map(|_| REDACTED.to_string()),
+            },
+            Destination::Gotify { options, .. } => Destination::Gotify {
+                token: REDACTED.to_string(),
+                options: options.clone(),
+            },
+            destination => destination.clone(),
+        }
+    }
+
+    // a URL from webhook_url with the token part replaced, ex: the last segment of Discord and Slack webhook URLs
+    pub fn redact_url(&self, url: &str) -> String {
+        let (url, query) = match url.split_once('?') {
+            Some((url, query)) => (url, Some(query)),
+            None => (url, None),
+        };
+        let mut segments = url.split('/').map(str::to_string).collect::<Vec<_>>();
+        match self {
+            Destination::Discord | Destination::Slack => {
+                if let Some(token) = segments.iter_mut().rev().find(|s| !s.is_empty()) {
+                    *token = REDACTED.to_string();
+                }
+            }
+            Destination::Telegram { .. } => {
+                for segment in segments.iter_mut().filter(|s| s.starts_with("bot")) {
+                    *segment = format!("bot{}", REDACTED);
+                }
+            }
+            _ => {}
+        }
+        let url = segments.join("/");
+        match query {
+            Some(query) => format!("{}?{}", url, query),
+            None => url,
+        }
+    }
+
+    // an inbound path from_path understands with the token part replaced,
+    // the same as its webhook URL except Gotify, whose token is only in the path
+    pub fn redact_path(&self, path: &str) -> String {
+        match self {
+            Destination::Gotify { .. } => match path.trim_end_matches('/').rsplit_once('/') {
+                Some((rest, _)) => format!("{}/{}", rest, REDACTED),
+                None => path.to_string(),
+            },
+            destination => destination.redact_url(path),
+        }
+    }
+}
+
+// what secrets are replaced with
+const REDACTED: &str = "[redacted]";
+
+// percent encode everything but unreserved characters, room ids contain ! and : which need escaping
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// undo percent encoding so room ids come out the same whether or not the sender escaped them
+fn decode_path_segment(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_path() {
+        assert_eq!(
+            Destination::from_path("api/webhooks/12345/abcdef"),
+            Destination::Discord
+        );
+        assert_eq!(
+            Destination::from_path("/services/T0001/B0001/XXXXXXXX"),
+            Destination::Slack
+        );
+        assert_eq!(
+            Destination::from_path("services/not/a/slack/path"),
+            Destination::Discord
+        );
+        assert_eq!(
+            Destination::from_path("bot123456:ABCDEF/-1001234"),
+            Destination::Telegram {
+                chat_id: "-1001234".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn redacts_tokens() {
+        assert_eq!(
+            Destination::Discord.redact_url("https://discord.com/api/webhooks/12345/abcdef"),
+            "https://discord.com/api/webhooks/12345/[redacted]"
+        );
+        assert_eq!(
+            Destination::Slack.redact_url("https://hooks.slack.com/services/T0001/B0001/XXXX"),
+            "https://hooks.slack.com/services/T0001/B0001/[redacted]"
+        );
+        let telegram = Destination::from_path("bot123456:ABCDEF/-1001234");
+        assert_eq!(
+            telegram.redact_url("https://api.telegram.org/bot123456:ABCDEF/sendMessage"),
+            "https://api.telegram.org/bot[redacted]/sendMessage"
+        );
+        let gotify = Destination::from_path("gotify/secret");
+        assert_eq!(
+            gotify.redacted(),
+            Destination::Gotify {
+                token: "[redacted]".to_string(),
+                options: PushOptions::default()
+            }
+        );
+        assert_eq!(
+            Destination::Json.redact_url("http://localhost/hooks/media"),
+            "http://localhost/hooks/media"
+        );
+
+        assert_eq!(
+            Destination::Discord.redact_path("api/webhooks/12345/abcdef"),
+            "api/webhooks/12345/[redacted]"
+        );
+        assert_eq!(
+            telegram.redact_path("bot123456:ABCDEF/-1001234"),
+            "bot[redacted]/-1001234"
+        );
+        assert_eq!(gotify.redact_path("gotify/secret"), "gotify/[redacted]");
+        assert_eq!(
+            Destination::from_path("ntfy/alerts").redact_path("ntfy/alerts"),
+            "ntfy/alerts"
+        );
+    }
+
+    #[test]
+    fn matrix_from_path() {
+        let matrix = Destination::from_path(
+            "_matrix/client/v3/rooms/!abcdef:example.org/send/m.room.message",
+        );
+        assert_eq!(
+            matrix,
+            Destination::Matrix {
+                room_id: "!abcdef:example.org".to_string(),
+                access_token: None
+            }
+        );
+        assert_eq!(
+            Destination::from_path(
+                "_matrix/client/v3/rooms/%21abcdef%3Aexample.org/send/m.room.message"
+            ),
+            matrix
+        );
+        assert_eq!(
+            matrix.webhook_url("http://localhost:8008/", ""),
+            "http://localhost:8008/_matrix/client/v3/rooms/%21abcdef%3Aexample.org/send/m.room.message"
+        );
+    }
+
+    #[test]
+    fn push_from_path() {
+        assert_eq!(
+            Destination::from_path("ntfy/hookbuffer"),
+            Destination::Ntfy {
+                topic: "hookbuffer".to_string(),
+                options: PushOptions::default()
+            }
+        );
+        let gotify = Destination::from_path("gotify/AbCdEf");
+        assert_eq!(
+            gotify,
+            Destination::Gotify {
+                token: "AbCdEf".to_string(),
+                options: PushOptions::default()
+            }
+        );
+        assert_eq!(
+            gotify.webhook_url("http://gotify.local/", "gotify/AbCdEf"),
+            "http://gotify.local/message"
+        );
+    }
+
+    #[test]
+    fn json_from_path() {
+        let json = Destination::from_path("/json/automation/hookbuffer");
+        assert_eq!(json, Destination::Json);
+        assert_eq!(
+            json.webhook_url("http://n8n.local/webhook/", "/json/automation/hookbuffer"),
+            "http://n8n.local/webhook/automation/hookbuffer"
+        );
+    }
+
+    #[test]
+    fn webhook_url() {
+        let telegram = Destination::from_path("bot123456:ABCDEF/-1001234");
+        assert_eq!(
+            telegram.webhook_url("https://api.telegram.org/", "bot123456:ABCDEF/-1001234"),
+            "https://api.telegram.org/bot123456:ABCDEF/sendMessage"
+        );
+        assert_eq!(
+            Destination::Discord.webhook_url("https://discord.com/", "/api/webhooks/1/abc"),
+            "https://discord.com/api/webhooks/1/abc"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use axum::{
+    body::Body,
+    http::{Response, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+
+use crate::source::SourceError;
+
+// why an incoming webhook was rejected
+// sent back to the application as {"error": "<code>", "message": "<details>"}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookbufferError {
+    // missing or wrong credentials or token
+    Unauthorized(String),
+    // an Authorization header that couldn't be read
+    InvalidAuthorization(String),
+    // the request didn't come from an application we know
+    UnsupportedUserAgent,
+    // the route is configured to reject this application
+    SourceNotAccepted(String),
+    // the body isn't JSON
+    InvalidJson(String),
+    // the body is JSON but not a payload the application would send
+    InvalidPayload(String),
+    // the payload parsed but it's an event type we don't handle
+    UnsupportedEventType,
+    // the admin API needs credentials to be configured before it can be used
+    AdminDisabled,
+    // an admin request that's missing something or asks for something that doesn't make sense
+    BadRequest(String),
+    // an admin request for a queue or dead letter that doesn't exist
+    NotFound(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl HookbufferError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            HookbufferError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            HookbufferError::SourceNotAccepted(_) | HookbufferError::AdminDisabled => {
+                StatusCode::FORBIDDEN
+            }
+            HookbufferError::UnsupportedEventType => StatusCode::UNPROCESSABLE_ENTITY,
+            HookbufferError::NotFound(_) => StatusCode::NOT_FOUND,
+            HookbufferError::InvalidAuthorization(_)
+            | HookbufferError::UnsupportedUserAgent
+            | HookbufferError::InvalidJson(_)
+            | HookbufferError::InvalidPayload(_)
+            | HookbufferError::BadRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    // stable name for the kind of error, for anything that wants to match on it
+    pub fn code(&self) -> &'static str {
+        match self {
+            HookbufferError::Unauthorized(_) => "unauthorized",
+            HookbufferError::InvalidAuthorization(_) => "invalid_authorization",
+            HookbufferError::UnsupportedUserAgent => "unsupported_user_agent",
+            HookbufferError::SourceNotAccepted(_) => "source_not_accepted",
+            HookbufferError::InvalidJson(_) => "invalid_json",
+            HookbufferError::InvalidPayload(_) => "invalid_payload",
+            HookbufferError::UnsupportedEventType => "unsupported_event_type",
+            HookbufferError::AdminDisabled => "admin_disabled",
+            HookbufferError::BadRequest(_) => "bad_request",
+            HookbufferError::NotFound(_) => "not_found",
+        }
+    }
+}
+
+impl std::fmt::Display for HookbufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookbufferError::Unauthorized(message)
+            | HookbufferError::InvalidAuthorization(message)
+            | HookbufferError::BadRequest(message)
+            | HookbufferError::NotFound(message) => write!(f, "{}", message),
+            HookbufferError::UnsupportedUserAgent => write!(f, "Received unsupported User-Agent"),
+            HookbufferError::SourceNotAccepted(source) => {
+                write!(f, "This route doesn't accept {} webhooks", source)
+            }
+            HookbufferError::InvalidJson(e) => write!(f, "Invalid JSON: {}", e),
+            HookbufferError::InvalidPayload(e) => write!(f, "Invalid payload: {}", e),
+            HookbufferError::UnsupportedEventType => write!(f, "Unsupported event type"),
+            HookbufferError::AdminDisabled => {
+                write!(
+                    f,
+                    "The admin API is disabled until credentials are configured"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for HookbufferError {}
+
+impl From<SourceError> for HookbufferError {
+    fn from(e: SourceError) -> Self {
+        match e {
+            SourceError::InvalidPayload(e) => HookbufferError::InvalidPayload(e.to_string()),
+            SourceError::UnsupportedEventType => HookbufferError::UnsupportedEventType,
+        }
+    }
+}
+
+impl IntoResponse for HookbufferError {
+    fn into_response(self) -> Response<Body> {
+        let body = ErrorBody {
+            error: self.code(),
+            message: self.to_string(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_errors_are_unprocessable_or_bad_requests() {
+        let invalid = serde_json::from_str::<u64>("\"abc\"").unwrap_err();
+        let e = HookbufferError::from(SourceError::InvalidPayload(invalid));
+        assert_eq!(e.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(e.code(), "invalid_payload");
+
+        let e = HookbufferError::from(SourceError::UnsupportedEventType);
+        assert_eq!(e.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
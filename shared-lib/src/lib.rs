@@ -1,3 +1,11 @@
 pub mod auth;
+pub mod clock;
+pub mod debounce;
+pub mod destination;
+pub mod error;
+pub mod rate_limit;
+pub mod retry;
 pub mod send;
+pub mod source;
 pub mod structs;
+pub mod template;
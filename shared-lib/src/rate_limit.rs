@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use serde::Serialize;
+
+use crate::clock::now_ms;
+
+// shared by every request this process sends
+pub static RATE_LIMITER: LazyLock<RateLimiter> = LazyLock::new(RateLimiter::default);
+
+// the rate limit headers Discord sends with every response
+// https://discord.com/developers/docs/topics/rate-limits#header-format
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RateLimitHeaders {
+    pub bucket: Option<String>,
+    pub remaining: Option<u64>,
+    pub reset_after: Option<Duration>,
+    pub retry_after: Option<Duration>,
+    // the limit applies to everything sent, not just this webhook
+    pub global: bool,
+}
+
+impl From<&HeaderMap> for RateLimitHeaders {
+    fn from(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let seconds = |name: &str| {
+            header(name)
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                .map(Duration::from_secs_f64)
+        };
+
+        RateLimitHeaders {
+            bucket: header("X-RateLimit-Bucket").map(str::to_string),
+            remaining: header("X-RateLimit-Remaining").and_then(|value| value.parse().ok()),
+            reset_after: seconds("X-RateLimit-Reset-After"),
+            retry_after: seconds("Retry-After"),
+            global: header("X-RateLimit-Global") == Some("true")
+                || header("X-RateLimit-Scope") == Some("global"),
+        }
+    }
+}
+
+// how many URLs the limiter remembers the bucket of before forgetting the ones that aren't limited
+const MAX_ROUTES: usize = 1024;
+
+#[derive(Debug)]
+struct Bucket {
+    remaining: u64,
+    reset_at: u64,
+    // host the requests were sent to, kept for the snapshot so it doesn't need the URL
+    host: String,
+    // Discord reported an id for it, otherwise it's named after its URL
+    named: bool,
+}
+
+// Discord's limits apply per bucket and webhook, so two webhooks reporting the same bucket id still have their own limits
+// buckets without an id are named after their URL
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BucketKey {
+    bucket: String,
+    major: String,
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    // Discord shares buckets between routes, so each URL is mapped to the bucket it reported
+    route_buckets: HashMap<String, String>,
+    buckets: HashMap<BucketKey, Bucket>,
+    // until when every request to a host has to wait because of a global limit,
+    // a global limit from Discord doesn't hold up other destinations
+    global_reset_at: HashMap<String, u64>,
+}
+
+impl LimiterState {
+    fn key(&self, url: &str) -> Option<BucketKey> {
+        let bucket = self.route_buckets.get(url)?;
+        Some(BucketKey {
+            bucket: bucket.clone(),
+            major: major(url).to_string(),
+        })
+    }
+
+    // forget limits that already reset, and everything if there are still too many of them
+    fn prune(&mut self, now: u64) {
+        if self.route_buckets.len() <= MAX_ROUTES && self.buckets.len() <= MAX_ROUTES {
+            return;
+        }
+        self.buckets.retain(|_, bucket| bucket.reset_at > now);
+        let buckets = &self.buckets;
+        self.route_buckets.retain(|url, bucket| {
+            buckets.contains_key(&BucketKey {
+                bucket: bucket.clone(),
+                major: major(url).to_string(),
+            })
+        });
+        if self.route_buckets.len() > MAX_ROUTES || self.buckets.len() > MAX_ROUTES {
+            self.route_buckets.clear();
+            self.buckets.clear();
+        }
+    }
+}
+
+// the part of a URL its limits are shared by: the webhook id for Discord webhooks, otherwise the URL without its query
+fn major(url: &str) -> &str {
+    match url.split_once("/webhooks/") {
+        Some((_, rest)) => rest.split(['/', '?']).next().unwrap_or(rest),
+        None => url.split('?').next().unwrap_or(url),
+    }
+}
+
+fn host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+// the limits currently in effect, without the URLs they apply to since those hold webhook tokens
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct RateLimitSnapshot {
+    // hosts every request to has to wait because of a global limit
+    pub global: Vec<GlobalLimit>,
+    pub buckets: Vec<BucketStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GlobalLimit {
+    pub host: String,
+    pub reset_in_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketStatus {
+    // host the requests were sent to, ex: discord.com
+    pub host: String,
+    // the bucket id Discord reported, if it sent one
+    pub bucket: Option<String>,
+    pub remaining: u64,
+    pub reset_in_ms: u64,
+}
+
+// tracks rate limits per bucket so requests wait before they'd be rejected instead of after
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    // how long to wait before sending a request to url, zero means it can be sent now
+    // and takes one of the requests the bucket has left, so concurrent senders don't all use the same one
+    pub fn acquire(&self, url: &str) -> Duration {
+        self.acquire_at(url, now_ms())
+    }
+
+    // remember the limits a response reported for url
+    pub fn update(&self, url: &str, headers: &RateLimitHeaders) {
+        self.update_at(url, headers, now_ms())
+    }
+
+    // a request to url was rejected with a 429, nothing else gets sent to it (or its host, if global) until retry_after passes
+    pub fn limited(&self, url: &str, retry_after: Duration, global: bool) {
+        self.limited_at(url, retry_after, global, now_ms())
+    }
+
+    pub fn snapshot(&self) -> RateLimitSnapshot {
+        self.snapshot_at(now_ms())
+    }
+
+    fn snapshot_at(&self, now: u64) -> RateLimitSnapshot {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut buckets = state
+            .buckets
+            .iter()
+            // limits that already reset don't matter anymore
+            .filter(|(_, bucket)| bucket.reset_at > now)
+            .map(|(key, bucket)| BucketStatus {
+                host: bucket.host.clone(),
+                // buckets without an id are named after their URL, which isn't shown
+                bucket: bucket.named.then(|| key.bucket.clone()),
+                remaining: bucket.remaining,
+                reset_in_ms: bucket.reset_at - now,
+            })
+            .collect::<Vec<_>>();
+        buckets.sort_by(|a, b| {
+            (&a.host, &a.bucket, a.reset_in_ms).cmp(&(&b.host, &b.bucket, b.reset_in_ms))
+        });
+
+        let mut global = state
+            .global_reset_at
+            .iter()
+            .filter(|(_, reset_at)| **reset_at > now)
+            .map(|(host, reset_at)| GlobalLimit {
+                host: host.clone(),
+                reset_in_ms: reset_at - now,
+            })
+            .collect::<Vec<_>>();
+        global.sort_by(|a, b| a.host.cmp(&b.host));
+
+        RateLimitSnapshot { global, buckets }
+    }
+
+    fn acquire_at(&self, url: &str, now: u64) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let global_wait = state
+            .global_reset_at
+            .get(&host(url))
+            .map_or(0, |reset_at| reset_at.saturating_sub(now));
+        if global_wait > 0 {
+            return Duration::from_millis(global_wait);
+        }
+        let Some(key) = state.key(url) else {
+            return Duration::ZERO;
+        };
+        match state.buckets.get_mut(&key) {
+            // a bucket that already reset is full again, the next response says by how much
+            Some(bucket) if bucket.reset_at > now => {
+                if bucket.remaining == 0 {
+                    return Duration::from_millis(bucket.reset_at - now);
+                }
+                bucket.remaining -= 1;
+                Duration::ZERO
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn update_at(&self, url: &str, headers: &RateLimitHeaders, now: u64) {
+        let (Some(remaining), Some(reset_after)) = (headers.remaining, headers.reset_after) else {
+            return;
+        };
+        // without a bucket id the URL is its own bucket
+        let named = headers.bucket.is_some();
+        let bucket = headers.bucket.clone().unwrap_or_else(|| url.to_string());
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.route_buckets.insert(url.to_string(), bucket.clone());
+        state.buckets.insert(
+            BucketKey {
+                bucket,
+                major: major(url).to_string(),
+            },
+            Bucket {
+                remaining,
+                reset_at: now + reset_after.as_millis() as u64,
+                host: host(url),
+                named,
+            },
+        );
+        state.prune(now);
+    }
+
+    fn limited_at(&self, url: &str, retry_after: Duration, global: bool, now: u64) {
+        let reset_at = now + retry_after.as_millis() as u64;
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if global {
+            state.global_reset_at.retain(|_, reset_at| *reset_at > now);
+            let global_reset_at = state.global_reset_at.entry(host(url)).or_default();
+            *global_reset_at = (*global_reset_at).max(reset_at);
+            return;
+        }
+        let bucket = state
+            .route_buckets
+            .entry(url.to_string())
+            .or_insert_with(|| url.to_string())
+            .clone();
+        let named = bucket != url;
+        state.buckets.insert(
+            BucketKey {
+                bucket,
+                major: major(url).to_string(),
+            },
+            Bucket {
+                remaining: 0,
+                reset_at,
+                host: host(url),
+                named,
+            },
+        );
+        state.prune(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    const WEBHOOK: &str = "https://discord.com/api/webhooks/1/abc";
+    const OTHER_WEBHOOK: &str = "https://discord.com/api/webhooks/2/def";
+
+    #[test]
+    fn parses_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Bucket", HeaderValue::from_static("abcd1234"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        headers.insert("X-RateLimit-Reset-After", HeaderValue::from_static("1.5"));
+        headers.insert("X-RateLimit-Scope", HeaderValue::from_static("user"));
+
+        assert_eq!(
+            RateLimitHeaders::from(&headers),
+            RateLimitHeaders {
+                bucket: Some("abcd1234".to_string()),
+                remaining: Some(0),
+                reset_after: Some(Duration::from_millis(1500)),
+                retry_after: None,
+                global: false,
+            }
+        );
+    }
+
+    #[test]
+    fn waits_for_exhausted_bucket() {
+        let limiter = RateLimiter::default();
+        let headers = |remaining| RateLimitHeaders {
+            bucket: Some("abcd1234".to_string()),
+            remaining: Some(remaining),
+            reset_after: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+
+        limiter.update_at(WEBHOOK, &headers(1), 1000);
+        assert_eq!(limiter.acquire_at(WEBHOOK, 1000), Duration::ZERO);
+
+        limiter.update_at(WEBHOOK, &headers(0), 1000);
+        assert_eq!(
+            limiter.acquire_at(WEBHOOK, 1500),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(limiter.acquire_at(WEBHOOK, 4000), Duration::ZERO);
+        // other webhooks have their own buckets
+        assert_eq!(limiter.acquire_at(OTHER_WEBHOOK, 1500), Duration::ZERO);
+    }
+
+    #[test]
+    fn webhooks_sharing_a_bucket_id_have_their_own_limits() {
+        let limiter = RateLimiter::default();
+        let headers = |remaining| RateLimitHeaders {
+            bucket: Some("abcd1234".to_string()),
+            remaining: Some(remaining),
+            reset_after: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+
+        limiter.update_at(WEBHOOK, &headers(0), 1000);
+        limiter.update_at(OTHER_WEBHOOK, &headers(5), 1000);
+        assert_eq!(limiter.acquire_at(WEBHOOK, 1000), Duration::from_secs(2));
+        assert_eq!(limiter.acquire_at(OTHER_WEBHOOK, 1000), Duration::ZERO);
+        // the same webhook with a thread_id is still the same webhook
+        assert_eq!(
+            limiter.acquire_at(&format!("{}?thread_id=1", WEBHOOK), 1000),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn concurrent_sends_use_up_the_bucket() {
+        let limiter = RateLimiter::default();
+        limiter.update_at(
+            WEBHOOK,
+            &RateLimitHeaders {
+                bucket: Some("abcd1234".to_string()),
+                remaining: Some(2),
+                reset_after: Some(Duration::from_secs(2)),
+                ..Default::default()
+            },
+            1000,
+        );
+
+        // two sends before either response arrives get through, the third waits
+        assert_eq!(limiter.acquire_at(WEBHOOK, 1000), Duration::ZERO);
+        assert_eq!(limiter.acquire_at(WEBHOOK, 1000), Duration::ZERO);
+        assert_eq!(limiter.acquire_at(WEBHOOK, 1000), Duration::from_secs(2));
+        // until the bucket resets
+        assert_eq!(limiter.acquire_at(WEBHOOK, 3000), Duration::ZERO);
+    }
+
+    #[test]
+    fn forgets_urls_past_the_cap() {
+        let limiter = RateLimiter::default();
+        let headers = RateLimitHeaders {
+            remaining: Some(5),
+            reset_after: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        for id in 0..MAX_ROUTES {
+            limiter.update_at(
+                &format!("https://discord.com/api/webhooks/{}/abc", id),
+                &headers,
+                0,
+            );
+        }
+        // once their limits reset they're dropped when another URL goes over the cap
+        limiter.update_at("https://discord.com/api/webhooks/5000/abc", &headers, 2000);
+
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.route_buckets.len(), 1);
+        assert_eq!(state.buckets.len(), 1);
+    }
+
+    #[test]
+    fn global_limits_apply_to_their_host() {
+        let limiter = RateLimiter::default();
+
+        limiter.limited_at(WEBHOOK, Duration::from_secs(1), false, 0);
+        assert_eq!(limiter.acquire_at(WEBHOOK, 0), Duration::from_secs(1));
+        assert_eq!(limiter.acquire_at(OTHER_WEBHOOK, 0), Duration::ZERO);
+
+        limiter.limited_at(WEBHOOK, Duration::from_secs(5), true, 0);
+        assert_eq!(limiter.acquire_at(OTHER_WEBHOOK, 0), Duration::from_secs(5));
+        // other destinations keep sending
+        assert_eq!(
+            limiter.acquire_at("https://hooks.slack.com/services/T0/B0/XXXX", 0),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.snapshot_at(1000).global,
+            vec![GlobalLimit {
+                host: "discord.com".to_string(),
+                reset_in_ms: 4000,
+            }]
+        );
+    }
+
+    #[test]
+    fn snapshot_hides_urls() {
+        let limiter = RateLimiter::default();
+        limiter.limited_at(WEBHOOK, Duration::from_secs(2), false, 0);
+
+        assert_eq!(
+            limiter.snapshot_at(500),
+            RateLimitSnapshot {
+                global: Vec::new(),
+                buckets: vec![BucketStatus {
+                    host: "discord.com".to_string(),
+                    bucket: None,
+                    remaining: 0,
+                    reset_in_ms: 1500,
+                }],
+            }
+        );
+        assert!(limiter.snapshot_at(3000).buckets.is_empty());
+    }
+}
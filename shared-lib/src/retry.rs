@@ -0,0 +1,106 @@
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+
+use axum::http::StatusCode;
+
+// how many times a request is sent before a network error or 5xx is given up on
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+// most time spent waiting between retries of one request
+pub const DEFAULT_MAX_RETRY_TIME: Duration = Duration::from_secs(60);
+// the first retry waits up to this long, doubling with every one after it
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+// set once at startup, shared by every request this process sends
+static POLICY: LazyLock<RwLock<RetryPolicy>> = LazyLock::new(RwLock::default);
+
+// when to retry requests that failed for a reason that might go away on its own,
+// 429s have their own backoff in shared_lib::send and other 4xx are never retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub max_retry_time: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            max_retry_time: DEFAULT_MAX_RETRY_TIME,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn current() -> Self {
+        *POLICY.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn install(self) {
+        *POLICY.write().unwrap_or_else(|e| e.into_inner()) = self;
+    }
+
+    // how long to wait before sending again after the given attempt failed, None once it's time to give up
+    // waited is the time already spent waiting on earlier retries of the same request
+    pub fn next_delay(&self, attempt: u32, waited: Duration) -> Option<Duration> {
+        let remaining = self.max_retry_time.saturating_sub(waited);
+        if attempt >= self.max_attempts || remaining.is_zero() {
+            return None;
+        }
+        Some(backoff(attempt).min(remaining))
+    }
+}
+
+// a 5xx might go away on its own, anything else the destination said won't
+pub fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+}
+
+// a random delay up to the exponential backoff for the attempt,
+// so requests that failed together don't all retry together
+fn backoff(attempt: u32) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    BASE_DELAY
+        .saturating_mul(1 << exponent)
+        .min(MAX_DELAY)
+        .mul_f64(random())
+}
+
+// a number from 0 to 1
+fn random() -> f64 {
+    let mut bytes = [0u8; 8];
+    // without randomness there's no jitter, but the backoff still applies
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return 1.0;
+    }
+    (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gives_up_after_attempts_or_time() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            max_retry_time: Duration::from_secs(10),
+        };
+
+        for attempt in 1..3 {
+            let delay = policy.next_delay(attempt, Duration::ZERO).unwrap();
+            assert!(delay <= BASE_DELAY * 2u32.pow(attempt - 1));
+        }
+        assert_eq!(policy.next_delay(3, Duration::ZERO), None);
+
+        // the last retry is cut short to fit in the time left
+        assert!(
+            policy.next_delay(1, Duration::from_millis(9_900)).unwrap()
+                <= Duration::from_millis(100)
+        );
+        assert_eq!(policy.next_delay(1, Duration::from_secs(10)), None);
+
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+    }
+}
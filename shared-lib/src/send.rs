@@ -1,89 +1,673 @@
 use axum::http::StatusCode;
-use reqwest::Client;
-use std::time::Duration;
+use reqwest::{Client, Method};
+use serde::Serialize;
+use serde_json::Value;
+use std::{fmt::Debug, sync::LazyLock, time::Duration};
 
-use crate::structs::discord::DiscordWebhookBody;
+use crate::{
+    clock,
+    destination::Destination,
+    rate_limit::{RateLimitHeaders, RATE_LIMITER},
+    retry::{is_retryable, RetryPolicy},
+    structs::{
+        discord::{DiscordWebhook, DiscordWebhookBody},
+        gotify::GotifyMessage,
+        matrix::MatrixMessage,
+        ntfy::NtfyMessage,
+        slack::SlackWebhookBody,
+        telegram::TelegramMessage,
+    },
+};
 
-pub async fn send_post_request(
+// give up on a destination that doesn't answer, so it can be retried
+#[cfg(not(target_arch = "wasm32"))]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+#[cfg(not(target_arch = "wasm32"))]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// one client for every request so connections to the same host are reused
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    #[cfg(target_arch = "wasm32")]
+    return Client::new();
+});
+
+// longest part of an error response kept in the error message
+const MAX_ERROR_BODY: usize = 300;
+
+// why a send was given up on
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendError {
+    // what the destination last responded with, None if it couldn't be reached
+    pub status: Option<StatusCode>,
+    pub error: String,
+    // requests sent before giving up, counting retries
+    pub attempts: u32,
+    // messages of a batch split over several that have been delivered, see DiscordWebhook::sent_parts
+    pub sent_parts: usize,
+}
+
+impl SendError {
+    fn new(status: StatusCode, error: impl Into<String>, attempts: u32) -> Self {
+        SendError {
+            status: Some(status),
+            error: error.into(),
+            attempts,
+            sent_parts: 0,
+        }
+    }
+
+    // the status to report when a single code is needed, unreachable destinations count as a 500
+    pub fn status(&self) -> StatusCode {
+        self.status.unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+// render the webhook body for the given destination and send it
+pub async fn send_webhook(webhook: DiscordWebhook) -> Result<StatusCode, SendError> {
+    let destination = webhook.destination.name();
+    let result = send_to_destination(webhook).await;
+    record_result(
+        destination,
+        result.as_ref().copied().map_err(SendError::status),
+    );
+    result
+}
+
+// count sent and failed webhooks by destination and status for the metrics endpoint
+#[cfg_attr(not(feature = "standalone"), allow(unused_variables))]
+fn record_result(destination: &'static str, result: Result<StatusCode, StatusCode>) {
+    #[cfg(feature = "standalone")]
+    match result {
+        Ok(status) => metrics::counter!(
+            "hookbuffer_webhooks_sent_total",
+            "destination" => destination,
+            "status" => status.as_u16().to_string()
+        )
+        .increment(1),
+        Err(status) => metrics::counter!(
+            "hookbuffer_webhooks_failed_total",
+            "destination" => destination,
+            "status" => status.as_u16().to_string()
+        )
+        .increment(1),
+    }
+}
+
+async fn send_to_destination(webhook: DiscordWebhook) -> Result<StatusCode, SendError> {
+    let DiscordWebhook {
+        url,
+        body,
+        destination,
+        batch_id,
+        event_type,
+        document,
+        message_id,
+        sent_parts,
+    } = webhook;
+
+    match destination {
+        // edits replace the whole message, so they're never split
+        Destination::Discord if message_id.is_some() => {
+            let url = discord_message_url(&url, message_id.as_deref().unwrap_or_default());
+            send_request(Method::PATCH, url, None, body).await
+        }
+        Destination::Discord => send_parts(&url, body.paginate(), sent_parts).await,
+        Destination::Slack => send_post_request(url, SlackWebhookBody::from(&body)).await,
+        Destination::Telegram { chat_id } => {
+            send_parts(
+                &url,
+                TelegramMessage::from_discord(&chat_id, &body),
+                sent_parts,
+            )
+            .await
+        }
+        Destination::Matrix { access_token, .. } => {
+            // the homeserver ignores repeats of a transaction id it already handled, so retries can't double post
+            let url = format!("{}/hookbuffer-{}", url, batch_id);
+            send_request(
+                Method::PUT,
+                url,
+                access_token.as_deref(),
+                MatrixMessage::from(&body),
+            )
+            .await
+        }
+        Destination::Ntfy { topic, options } => {
+            send_post_request(url, NtfyMessage::new(&topic, &body, &event_type, &options)).await
+        }
+        Destination::Gotify { token, options } => {
+            send_request(
+                Method::POST,
+                url,
+                Some(&token),
+                GotifyMessage::new(&body, &event_type, &options),
+            )
+            .await
+        }
+        Destination::Json => match document {
+            Some(document) => send_post_request(url, document).await,
+            // webhooks queued without a document can't be sent as one
+            None => Err(SendError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Webhook was queued without a JSON document",
+                0,
+            )),
+        },
+    }
+}
+
+// send the messages a batch was split into, skipping the ones an earlier try already delivered
+// and stopping at the first one that fails so its error says how many made it
+async fn send_parts<T: Serialize + Debug>(
+    url: &str,
+    parts: Vec<T>,
+    sent_parts: usize,
+) -> Result<StatusCode, SendError> {
+    // what the last part sent was answered with, OK if an earlier try already sent them all
+    let mut status = StatusCode::OK;
+    for (part, message) in parts.into_iter().enumerate().skip(sent_parts) {
+        status = send_post_request(url.to_string(), message)
+            .await
+            .map_err(|e| SendError {
+                sent_parts: part,
+                ..e
+            })?;
+    }
+    Ok(status)
+}
+
+// post a message to a Discord webhook and return the status along with the id of the message it created
+// so it can be edited later
+pub async fn post_discord_message(
     url: String,
     body: DiscordWebhookBody,
-) -> Result<StatusCode, StatusCode> {
-    #[cfg(feature = "worker")]
-    worker::console_log!("Sending POST request to {} with payload: {:?}", url, body);
+) -> Result<(StatusCode, String), SendError> {
+    // without wait Discord doesn't send the message back
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let url = format!("{}{}wait=true", url, separator);
+    let result = send_request_json(Method::POST, url, None, body)
+        .await
+        .and_then(|(status, message)| match message["id"].as_str() {
+            Some(id) => Ok((status, id.to_string())),
+            None => Err(SendError::new(
+                StatusCode::BAD_GATEWAY,
+                "Discord didn't return the id of the message it sent",
+                1,
+            )),
+        });
+    record_result(
+        Destination::Discord.name(),
+        result
+            .as_ref()
+            .map(|(status, _)| *status)
+            .map_err(SendError::status),
+    );
+    result
+}
+
+// the URL of a message sent through a webhook, keeping query params like thread_id
+fn discord_message_url(url: &str, message_id: &str) -> String {
+    match url.split_once('?') {
+        Some((webhook_url, query)) => format!("{}/messages/{}?{}", webhook_url, message_id, query),
+        None => format!("{}/messages/{}", url, message_id),
+    }
+}
+
+pub async fn send_post_request<T: Serialize + Debug>(
+    url: String,
+    body: T,
+) -> Result<StatusCode, SendError> {
+    send_request(Method::POST, url, None, body).await
+}
+
+async fn send_request<T: Serialize + Debug>(
+    method: Method,
+    url: String,
+    bearer_token: Option<&str>,
+    body: T,
+) -> Result<StatusCode, SendError> {
+    send_request_json(method, url, bearer_token, body)
+        .await
+        .map(|(status, _)| status)
+}
+
+// send a request and return the status and JSON the destination responded with, null if there wasn't any
+async fn send_request_json<T: Serialize + Debug>(
+    method: Method,
+    url: String,
+    bearer_token: Option<&str>,
+    body: T,
+) -> Result<(StatusCode, Value), SendError> {
+    #[cfg(all(feature = "worker", target_arch = "wasm32"))]
+    worker::console_log!(
+        "Sending {} request to {} with payload: {:?}",
+        method,
+        url,
+        body
+    );
     #[cfg(feature = "standalone")]
-    tracing::info!("Sending POST request to {} with payload: {:?}", url, body);
+    tracing::info!(
+        "Sending {} request to {} with payload: {:?}",
+        method,
+        url,
+        body
+    );
 
+    // only used when a 429 doesn't say how long to wait
     let mut backoff = Duration::from_secs(4); // start with a 4 second delay
     let backoff_limit = 128;
 
-    let client = Client::new();
+    // network errors and 5xx responses are retried separately from 429s
+    let policy = RetryPolicy::current();
+    let mut failures = 0;
+    let mut waited = Duration::ZERO;
+    // every request sent, including the ones that were rate limited
+    let mut attempts = 0;
 
     loop {
-        match client.post(url.clone()).json(&body).send().await {
+        // wait out any limit we already know about instead of sending a request that will be rejected,
+        // checking again after waiting since other sends may have used up the bucket in the meantime
+        loop {
+            let wait = RATE_LIMITER.acquire(&url);
+            if wait.is_zero() {
+                break;
+            }
+            #[cfg(all(feature = "worker", target_arch = "wasm32"))]
+            worker::console_log!(
+                "Waiting {}ms for rate limit before sending to {}",
+                wait.as_millis(),
+                url
+            );
+            #[cfg(feature = "standalone")]
+            tracing::info!(
+                "Waiting {}ms for rate limit before sending to {}",
+                wait.as_millis(),
+                url
+            );
+            clock::sleep(wait).await;
+        }
+
+        let mut request = CLIENT.request(method.clone(), url.clone()).json(&body);
+        if let Some(token) = bearer_token {
+            request = request.bearer_auth(token);
+        }
+
+        attempts += 1;
+        let error = match request.send().await {
             Err(e) => {
-                #[cfg(feature = "worker")]
+                #[cfg(all(feature = "worker", target_arch = "wasm32"))]
                 worker::console_error!(
-                    "Failed to send POST request to {}. Error: {:?}, payload: {:?}",
+                    "Failed to send {} request to {}. Error: {:?}, payload: {:?}",
+                    method,
                     url,
                     e,
                     body
                 );
                 #[cfg(feature = "standalone")]
                 tracing::error!(
-                    "Failed to send POST request to {}. Error: {:?}, payload: {:?}",
+                    "Failed to send {} request to {}. Error: {:?}, payload: {:?}",
+                    method,
                     url,
                     e,
                     body
                 );
 
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                SendError {
+                    status: None,
+                    error: format!("Couldn't reach the destination: {}", e.without_url()),
+                    attempts,
+                    sent_parts: 0,
+                }
             }
             Ok(response) if response.status().is_success() => {
-                return Ok(StatusCode::OK);
+                RATE_LIMITER.update(&url, &RateLimitHeaders::from(response.headers()));
+                let status = response.status();
+                return Ok((status, response.json::<Value>().await.unwrap_or_default()));
             }
             Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
-                #[cfg(feature = "worker")]
+                let status = response.status();
+                let headers = RateLimitHeaders::from(response.headers());
+                RATE_LIMITER.update(&url, &headers);
+
+                // Discord sends Retry-After, Telegram and Matrix say how long to wait in the body
+                let body_json = response.json::<Value>().await.unwrap_or_default();
+                let retry_after = headers.retry_after.or_else(|| {
+                    body_json["retry_after"]
+                        .as_f64()
+                        .map(Duration::from_secs_f64)
+                        .or_else(|| {
+                            body_json["parameters"]["retry_after"]
+                                .as_u64()
+                                .map(Duration::from_secs)
+                        })
+                        .or_else(|| {
+                            body_json["retry_after_ms"]
+                                .as_u64()
+                                .map(Duration::from_millis)
+                        })
+                });
+                let global = headers.global || body_json["global"].as_bool().unwrap_or(false);
+                let delay = retry_after.unwrap_or(backoff);
+                RATE_LIMITER.limited(&url, delay, global);
+
+                #[cfg(feature = "standalone")]
+                {
+                    metrics::counter!("hookbuffer_rate_limit_retries_total").increment(1);
+                    metrics::histogram!("hookbuffer_rate_limit_backoff_seconds")
+                        .record(delay.as_secs_f64());
+                }
+
+                #[cfg(all(feature = "worker", target_arch = "wasm32"))]
                 worker::console_warn!(
-                    "Rate limited. Retrying in {} seconds. Failed to send POST request to {}. Status: {}, payload: {:?}",
-                    backoff.as_secs(),
+                    "Rate limited. Retrying in {} seconds. Failed to send {} request to {}. Status: {}, payload: {:?}",
+                    delay.as_secs(),
+                    method,
                     url,
-                    response.status(),
+                    status,
                     body
                 );
                 #[cfg(feature = "standalone")]
                 tracing::warn!(
-                    "Rate limited. Retrying in {} seconds. Failed to send POST request to {}. Status: {}, payload: {:?}",
-                    backoff.as_secs(),
+                    "Rate limited. Retrying in {} seconds. Failed to send {} request to {}. Status: {}, payload: {:?}",
+                    delay.as_secs(),
+                    method,
                     url,
-                    response.status(),
+                    status,
                     body
                 );
 
-                tokio::time::sleep(backoff).await;
-
+                // the wait happens at the top of the loop, where the limiter now knows about it
                 if (backoff * 2).as_secs() > backoff_limit {
-                    return Err(StatusCode::TOO_MANY_REQUESTS);
+                    return Err(SendError::new(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Still rate limited after backing off",
+                        attempts,
+                    ));
                 } else {
                     backoff *= 2;
                 }
+                continue;
             }
             Ok(response) => {
-                #[cfg(feature = "worker")]
+                let status = response.status();
+                #[cfg(all(feature = "worker", target_arch = "wasm32"))]
                 worker::console_error!(
-                    "Failed to send POST request to {}. Status: {}, payload: {:?}",
+                    "Failed to send {} request to {}. Status: {}, payload: {:?}",
+                    method,
                     url,
-                    response.status(),
+                    status,
                     body
                 );
                 #[cfg(feature = "standalone")]
                 tracing::error!(
-                    "Failed to send POST request to {}. Status: {}, payload: {:?}",
+                    "Failed to send {} request to {}. Status: {}, payload: {:?}",
+                    method,
                     url,
-                    response.status(),
+                    status,
                     body
                 );
-                return Err(response.status());
+
+                // keep what the destination said went wrong, cut short in case it sent a whole page
+                let text = response.text().await.unwrap_or_default();
+                let text: String = text.trim().chars().take(MAX_ERROR_BODY).collect();
+                let error = match text.is_empty() {
+                    true => format!("Destination responded with {}", status),
+                    false => format!("Destination responded with {}: {}", status, text),
+                };
+                // the destination won't accept it no matter how often it's sent
+                if !is_retryable(status) {
+                    return Err(SendError::new(status, error, attempts));
+                }
+                SendError::new(status, error, attempts)
             }
+        };
+
+        // network errors and 5xx might go away on their own
+        failures += 1;
+        let Some(delay) = policy.next_delay(failures, waited) else {
+            return Err(error);
+        };
+        waited += delay;
+
+        #[cfg(feature = "standalone")]
+        metrics::counter!("hookbuffer_delivery_retries_total").increment(1);
+
+        #[cfg(all(feature = "worker", target_arch = "wasm32"))]
+        worker::console_warn!(
+            "Retrying {} request to {} in {}ms, attempt {} of {} failed: {}",
+            method,
+            url,
+            delay.as_millis(),
+            failures,
+            policy.max_attempts,
+            error.error
+        );
+        #[cfg(feature = "standalone")]
+        tracing::warn!(
+            "Retrying {} request to {} in {}ms, attempt {} of {} failed: {}",
+            method,
+            url,
+            delay.as_millis(),
+            failures,
+            policy.max_attempts,
+            error.error
+        );
+
+        clock::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        extract::{Path, State},
+        http::HeaderMap,
+        routing::{post, put},
+        Json, Router,
+    };
+    use serde_json::json;
+    use twilight_model::channel::message::Embed;
+
+    use super::*;
+    use crate::structs::discord::DiscordWebhookBody;
+
+    type Requests = Arc<Mutex<Vec<(String, String, Option<String>)>>>;
+
+    // a homeserver that rate limits the first request it sees and accepts everything after that
+    async fn mock_homeserver() -> (String, Requests) {
+        let requests = Requests::default();
+        let app = Router::new()
+            .route(
+                "/_matrix/client/v3/rooms/{room_id}/send/m.room.message/{txn_id}",
+                put(
+                    |State(requests): State<Requests>,
+                     Path((room_id, txn_id)): Path<(String, String)>,
+                     headers: HeaderMap| async move {
+                        let authorization = headers
+                            .get("Authorization")
+                            .and_then(|h| h.to_str().ok())
+                            .map(str::to_string);
+                        let mut requests = requests.lock().unwrap();
+                        requests.push((room_id, txn_id, authorization));
+                        match requests.len() {
+                            1 => (
+                                StatusCode::TOO_MANY_REQUESTS,
+                                Json(
+                                    json!({ "errcode": "M_LIMIT_EXCEEDED", "retry_after_ms": 10 }),
+                                ),
+                            ),
+                            _ => (StatusCode::OK, Json(json!({ "event_id": "$event" }))),
+                        }
+                    },
+                ),
+            )
+            .with_state(Arc::clone(&requests));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}/", address), requests)
+    }
+
+    #[tokio::test]
+    async fn matrix_retries_reuse_transaction_id() {
+        let (base_url, requests) = mock_homeserver().await;
+        let destination = Destination::Matrix {
+            room_id: "!room:localhost".to_string(),
+            access_token: Some("secret".to_string()),
+        };
+        let body = DiscordWebhookBody {
+            content: "Imported: Fake Series Season 01".to_string(),
+            embeds: vec![Embed {
+                title: Some("Fake Series".to_string()),
+                color: Some(0x29A44C),
+                fields: Vec::new(),
+                kind: "rich".to_string(),
+                author: None,
+                description: Some("01x01 - Fake Episode 1 [Fake Quality]".to_string()),
+                footer: None,
+                image: None,
+                provider: None,
+                thumbnail: None,
+                timestamp: None,
+                url: None,
+                video: None,
+            }],
+        };
+        let webhook = DiscordWebhook::new(
+            destination.webhook_url(&base_url, ""),
+            body,
+            destination,
+            "sonarr-abc-123".to_string(),
+            "Download".to_string(),
+        );
+
+        assert_eq!(send_webhook(webhook).await, Ok(StatusCode::OK));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for (room_id, txn_id, authorization) in requests.iter() {
+            assert_eq!(room_id, "!room:localhost");
+            assert_eq!(txn_id, "hookbuffer-sonarr-abc-123");
+            assert_eq!(authorization.as_deref(), Some("Bearer secret"));
         }
     }
+
+    #[tokio::test]
+    async fn retries_server_errors_but_not_client_errors() {
+        let requests = Arc::new(Mutex::new(Vec::<String>::new()));
+        let app =
+            Router::new()
+                .route(
+                    "/{kind}",
+                    post(
+                        |State(requests): State<Arc<Mutex<Vec<String>>>>,
+                         Path(kind): Path<String>| async move {
+                            let mut requests = requests.lock().unwrap();
+                            requests.push(kind.clone());
+                            match (kind.as_str(), requests.len()) {
+                                ("flaky", 1) => StatusCode::SERVICE_UNAVAILABLE,
+                                ("flaky", _) => StatusCode::NO_CONTENT,
+                                _ => StatusCode::NOT_FOUND,
+                            }
+                        },
+                    ),
+                )
+                .with_state(Arc::clone(&requests));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = format!("http://{}/flaky", address);
+        // the status the destination answered with is passed on, not just OK
+        assert_eq!(
+            send_post_request(url, json!({})).await,
+            Ok(StatusCode::NO_CONTENT)
+        );
+        assert_eq!(requests.lock().unwrap().len(), 2);
+
+        requests.lock().unwrap().clear();
+        let url = format!("http://{}/missing", address);
+        let error = send_post_request(url, json!({})).await.unwrap_err();
+        assert_eq!(error.status, Some(StatusCode::NOT_FOUND));
+        assert_eq!(error.attempts, 1);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resending_skips_delivered_parts() {
+        // accepts everything except the second request it sees
+        let contents = Arc::new(Mutex::new(Vec::<String>::new()));
+        let app =
+            Router::new()
+                .route(
+                    "/webhook",
+                    post(
+                        |State(contents): State<Arc<Mutex<Vec<String>>>>,
+                         Json(body): Json<Value>| async move {
+                            let mut contents = contents.lock().unwrap();
+                            contents.push(body["content"].as_str().unwrap_or_default().to_string());
+                            match contents.len() {
+                                2 => StatusCode::NOT_FOUND,
+                                _ => StatusCode::NO_CONTENT,
+                            }
+                        },
+                    ),
+                )
+                .with_state(Arc::clone(&contents));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let embed = Embed {
+            title: Some("Fake Movie".to_string()),
+            color: Some(0x29A44C),
+            fields: Vec::new(),
+            kind: "rich".to_string(),
+            author: None,
+            description: Some("Fake Movie (2024) [Bluray-1080p]".to_string()),
+            footer: None,
+            image: None,
+            provider: None,
+            thumbnail: None,
+            timestamp: None,
+            url: None,
+            video: None,
+        };
+        let body = DiscordWebhookBody {
+            content: "Imported: 21 Movies".to_string(),
+            embeds: vec![embed; 21],
+        };
+        let mut webhook = DiscordWebhook::new(
+            format!("http://{}/webhook", address),
+            body,
+            Destination::Discord,
+            "radarr-abc-123".to_string(),
+            "Download".to_string(),
+        );
+
+        let error = send_webhook(webhook.clone()).await.unwrap_err();
+        assert_eq!(error.sent_parts, 1);
+
+        webhook.sent_parts = error.sent_parts;
+        assert_eq!(send_webhook(webhook).await, Ok(StatusCode::NO_CONTENT));
+        assert_eq!(
+            *contents.lock().unwrap(),
+            vec![
+                "Imported: 21 Movies (part 1/3)",
+                "Imported: 21 Movies (part 2/3)",
+                "Imported: 21 Movies (part 2/3)",
+                "Imported: 21 Movies (part 3/3)",
+            ]
+        );
+    }
 }
@@ -0,0 +1,245 @@
+use serde_json::Value;
+
+use super::{Delivery, Source, SourceError};
+use crate::structs::{
+    batch::{BatchAlbum, BatchArtist, BatchDetails, BatchTrack},
+    discord::{DiscordWebhookBody, RenderStyle},
+    lidarr::{LidarrEventType, LidarrGroupKey, LidarrRequestBody},
+};
+use crate::template::Templates;
+
+pub struct Lidarr;
+
+impl Source for Lidarr {
+    type Event = LidarrRequestBody;
+    type GroupKey = LidarrGroupKey;
+
+    const NAME: &'static str = "lidarr";
+    const USER_AGENT: &'static str = "Lidarr";
+
+    fn parse(body: Value) -> Result<Self::Event, SourceError> {
+        let mut lidarr_request: LidarrRequestBody =
+            serde_json::from_value(body).map_err(SourceError::InvalidPayload)?;
+
+        let event_type = match lidarr_request.event_type {
+            Some(LidarrEventType::Download) if lidarr_request.is_upgrade.unwrap_or(false) => {
+                LidarrEventType::Upgrade
+            }
+            Some(event_type) => event_type,
+            None => return Err(SourceError::UnsupportedEventType),
+        };
+        lidarr_request.event_type = Some(event_type);
+
+        match event_type {
+            // sent when the webhook is saved to check the connection
+            LidarrEventType::Test
+            | LidarrEventType::Grab
+            | LidarrEventType::Download
+            | LidarrEventType::Upgrade => Ok(lidarr_request),
+            _ => Err(SourceError::UnsupportedEventType),
+        }
+    }
+
+    fn group_key(event: &Self::Event) -> Self::GroupKey {
+        LidarrGroupKey::from(event)
+    }
+
+    fn event_type(event: &Self::Event) -> String {
+        format!("{:?}", event.event_type.unwrap_or(LidarrEventType::Test))
+    }
+
+    // movies and tracks aren't numbered in a way that collapses into ranges, so every style renders the same
+    // templates are only supported for Sonarr so far
+    fn render(
+        group: Vec<Self::Event>,
+        _style: RenderStyle,
+        _templates: Option<&Templates>,
+    ) -> DiscordWebhookBody {
+        DiscordWebhookBody::from(group)
+    }
+
+    fn describe(event: &Self::Event) -> String {
+        let album_title = event
+            .first_album()
+            .map(|album| album.title.as_str())
+            .unwrap_or("Unknown Album");
+        format!(
+            "{:?} Album: {} - {} ({} tracks)",
+            event.event_type.unwrap_or(LidarrEventType::Test),
+            event.artist.name,
+            album_title,
+            event.tracks.as_ref().map_or(0, |tracks| tracks.len())
+        )
+    }
+
+    fn instance(event: &Self::Event) -> Option<String> {
+        event.instance_name.clone()
+    }
+
+    fn details(group: &[Self::Event]) -> BatchDetails {
+        let artist = &group[0].artist;
+        let album = group[0].first_album().map(|album| BatchAlbum {
+            id: album.id,
+            title: album.title.clone(),
+            mb_id: album.mb_id.clone(),
+        });
+
+        let mut tracks: Vec<BatchTrack> = Vec::new();
+        for request in group {
+            let release_quality = request.release.as_ref().and_then(|r| r.quality.clone());
+            for track in request.tracks.iter().flatten() {
+                let quality = track.quality.clone().or_else(|| release_quality.clone());
+                match tracks.iter_mut().find(|batch_track| {
+                    batch_track.number == track.track_number
+                        && batch_track.title == track.title
+                        && batch_track.quality == quality
+                }) {
+                    Some(batch_track) => batch_track.count += 1,
+                    None => tracks.push(BatchTrack {
+                        number: track.track_number.clone(),
+                        title: track.title.clone(),
+                        quality,
+                        count: 1,
+                    }),
+                }
+            }
+        }
+
+        BatchDetails::Tracks {
+            artist: BatchArtist {
+                id: artist.id,
+                name: artist.name.clone(),
+                mb_id: artist.mb_id.clone(),
+            },
+            album,
+            tracks,
+        }
+    }
+
+    fn download(event: &Self::Event) -> Option<(String, usize)> {
+        let download_id = event.download_id.clone().filter(|id| !id.is_empty())?;
+        // grabs don't list tracks, so imports are counted per album
+        Some((download_id, 1))
+    }
+
+    // the connection test is answered right away, every other event type parse accepts is buffered
+    fn delivery(event: &Self::Event) -> Delivery {
+        match event.event_type {
+            Some(LidarrEventType::Test) => Delivery::Test,
+            _ => Delivery::Buffered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::{group_events, group_id};
+
+    fn lidarr_body(event_type: &str, is_upgrade: bool, album_id: u64) -> Value {
+        json!({
+            "eventType": event_type,
+            "isUpgrade": is_upgrade,
+            "artist": { "id": 1, "name": "Fake Artist" },
+            "album": { "id": album_id, "title": format!("Fake Album {}", album_id) },
+            "tracks": [
+                { "title": "Second Track", "trackNumber": "2", "quality": "FLAC" },
+                { "title": "First Track", "trackNumber": "1", "quality": "FLAC" }
+            ]
+        })
+    }
+
+    #[test]
+    fn parse_marks_upgrades() {
+        let event = Lidarr::parse(lidarr_body("Download", true, 1)).unwrap();
+        assert_eq!(event.event_type, Some(LidarrEventType::Upgrade));
+        assert_eq!(Lidarr::delivery(&event), Delivery::Buffered);
+
+        let event = Lidarr::parse(lidarr_body("Download", false, 1)).unwrap();
+        assert_eq!(event.event_type, Some(LidarrEventType::Download));
+    }
+
+    #[test]
+    fn parse_answers_connection_tests() {
+        let test = Lidarr::parse(json!({
+            "eventType": "Test",
+            "instanceName": "Lidarr",
+            "artist": { "id": 1, "name": "Test Artist" },
+            "albums": [{ "id": 1, "title": "Test title" }]
+        }))
+        .unwrap();
+        assert_eq!(Lidarr::delivery(&test), Delivery::Test);
+
+        assert!(matches!(
+            Lidarr::parse(lidarr_body("Retag", false, 1)),
+            Err(SourceError::UnsupportedEventType)
+        ));
+        assert!(matches!(
+            Lidarr::parse(json!({ "artist": { "name": "Fake Artist" } })),
+            Err(SourceError::UnsupportedEventType)
+        ));
+    }
+
+    #[test]
+    fn groups_by_album_and_event_type() {
+        let queue = vec![
+            Lidarr::parse(lidarr_body("Download", false, 1)).unwrap(),
+            Lidarr::parse(lidarr_body("Download", false, 1)).unwrap(),
+            Lidarr::parse(lidarr_body("Download", false, 2)).unwrap(),
+            Lidarr::parse(lidarr_body("Download", true, 2)).unwrap(),
+        ];
+
+        let groups = group_events::<Lidarr>(queue);
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(
+            groups[&LidarrGroupKey(1, 1, LidarrEventType::Download)].len(),
+            2
+        );
+        assert_ne!(
+            group_id::<Lidarr>(&LidarrGroupKey(1, 1, LidarrEventType::Download)),
+            group_id::<Lidarr>(&LidarrGroupKey(1, 2, LidarrEventType::Download))
+        );
+    }
+
+    #[test]
+    fn renders_tracks() {
+        let body = Lidarr::render(
+            vec![Lidarr::parse(lidarr_body("Download", false, 1)).unwrap()],
+            RenderStyle::Full,
+            None,
+        );
+        assert_eq!(body.content, "Imported: Fake Artist - Fake Album 1");
+        assert_eq!(body.embeds[0].title.as_deref(), Some("Fake Artist"));
+        assert_eq!(body.embeds[0].color, Some(0x29A44C));
+        assert_eq!(
+            body.message_lines(),
+            vec!["1 - First Track [FLAC]", "2 - Second Track [FLAC]"]
+        );
+
+        // grabs don't list tracks, so every album gets one line
+        let grab = Lidarr::parse(json!({
+            "eventType": "Grab",
+            "artist": { "id": 1, "name": "Fake Artist" },
+            "albums": [
+                { "id": 2, "title": "Second Album" },
+                { "id": 1, "title": "First Album" }
+            ],
+            "release": { "quality": "MP3-320" }
+        }))
+        .unwrap();
+        let body = Lidarr::render(vec![grab], RenderStyle::Full, None);
+        assert_eq!(body.content, "Grabbed: Fake Artist - 2 Albums");
+        assert_eq!(
+            body.message_lines(),
+            vec![
+                "**First Album**",
+                "First Album [MP3-320]",
+                "**Second Album**",
+                "Second Album [MP3-320]"
+            ]
+        );
+    }
+}
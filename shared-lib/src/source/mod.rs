@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
+
+use fnv::FnvHasher;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    destination::Destination,
+    structs::{
+        batch::{BatchDetails, BatchDocument, BATCH_DOCUMENT_VERSION},
+        discord::{DiscordWebhook, DiscordWebhookBody, RenderStyle},
+    },
+    template::Templates,
+};
+
+mod lidarr;
+mod radarr;
+mod sonarr;
+
+pub use lidarr::Lidarr;
+pub use radarr::Radarr;
+pub use sonarr::Sonarr;
+
+#[derive(Debug)]
+pub enum SourceError {
+    // the body couldn't be deserialized into the source's payload struct
+    InvalidPayload(serde_json::Error),
+    // the payload parsed but it's an event type we don't buffer
+    UnsupportedEventType,
+}
+
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceError::InvalidPayload(e) => write!(f, "Invalid payload: {}", e),
+            SourceError::UnsupportedEventType => {
+                write!(f, "Unsupported event type, must be Grab or Download")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+// what happens to an event once it's parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    // grouped with similar events and sent when the timer expires
+    Buffered,
+    // sent right away on its own, ex: health alerts
+    Immediate,
+    // the application checking the connection, acknowledged without sending anything
+    Test,
+}
+
+// an application that sends webhooks hookbuffer knows how to buffer
+// implementors only describe how to parse, group, and render their events,
+// the timers and queues are shared between all sources
+pub trait Source: Send + Sync + 'static {
+    type Event: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static;
+    type GroupKey: Copy + Debug + Eq + Hash + Ord + Send + 'static;
+
+    // used for logs and for namespacing stored queues, ex: "sonarr"
+    const NAME: &'static str;
+    // the User-Agent prefix the application sends with its webhooks, ex: "Sonarr"
+    const USER_AGENT: &'static str;
+
+    // parse a webhook body into an event, normalizing the event type and rejecting events we don't buffer
+    fn parse(body: Value) -> Result<Self::Event, SourceError>;
+
+    fn group_key(event: &Self::Event) -> Self::GroupKey;
+
+    // name of the event's type, ex: Grab, Download, Upgrade
+    fn event_type(event: &Self::Event) -> String;
+
+    fn render(
+        group: Vec<Self::Event>,
+        style: RenderStyle,
+        templates: Option<&Templates>,
+    ) -> DiscordWebhookBody;
+
+    // short human readable description of an event for logging
+    fn describe(event: &Self::Event) -> String;
+
+    // instanceName the application sent with the event, if any
+    fn instance(event: &Self::Event) -> Option<String>;
+
+    // the source specific part of the BatchDocument sent to JSON destinations
+    fn details(group: &[Self::Event]) -> BatchDetails;
+
+    // the download client's id for the release an event is about, and how many items the event covers
+    // used to match imports with the grab they came from
+    fn download(event: &Self::Event) -> Option<(String, usize)>;
+
+    fn delivery(event: &Self::Event) -> Delivery;
+}
+
+// convert all the events in the queue into a map of groupings based on the source's group key
+pub fn group_events<S: Source>(queue: Vec<S::Event>) -> HashMap<S::GroupKey, Vec<S::Event>> {
+    let mut grouped_events: HashMap<S::GroupKey, Vec<S::Event>> = HashMap::new();
+
+    for event in queue {
+        grouped_events
+            .entry(S::group_key(&event))
+            .or_default()
+            .push(event);
+    }
+
+    grouped_events
+}
+
+// stable id for a group key, the same key always gets the same id
+pub fn group_id<S: Source>(group_key: &S::GroupKey) -> String {
+    format!("{}-{}", S::NAME, stable_hash(group_key))
+}
+
+// a hash that's the same in every build, for ids that end up stored or sent somewhere
+// DefaultHasher's output can change between Rust releases
+pub fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// describe a group as a BatchDocument, the group must not be empty
+pub fn batch_document<S: Source>(group: &[S::Event], batch_id: String) -> BatchDocument {
+    BatchDocument {
+        version: BATCH_DOCUMENT_VERSION,
+        source: S::NAME.to_string(),
+        instance: S::instance(&group[0]),
+        group_key: group_id::<S>(&S::group_key(&group[0])),
+        batch_id,
+        event_type: S::event_type(&group[0]),
+        count: group.len(),
+        details: S::details(group),
+    }
+}
+
+// render a group into the webhook sent to the destination
+// flushed_at is when the group's timer expired, in milliseconds since the epoch
+pub fn build_webhook<S: Source>(
+    url: String,
+    destination: &Destination,
+    group: Vec<S::Event>,
+    flushed_at: u128,
+    style: RenderStyle,
+    templates: Option<&Templates>,
+) -> DiscordWebhook {
+    let batch_id = format!("{}-{}", group_id::<S>(&S::group_key(&group[0])), flushed_at);
+    let event_type = S::event_type(&group[0]);
+    // only JSON destinations use the document, skip it for the rest to keep queued messages small
+    let document = match destination {
+        Destination::Json => Some(batch_document::<S>(&group, batch_id.clone())),
+        _ => None,
+    };
+
+    let mut webhook = DiscordWebhook::new(
+        url,
+        S::render(group, style, templates),
+        destination.clone(),
+        batch_id,
+        event_type,
+    );
+    webhook.document = document;
+    webhook
+}
@@ -0,0 +1,215 @@
+use serde_json::Value;
+
+use super::{Delivery, Source, SourceError};
+use crate::structs::{
+    batch::{BatchDetails, BatchMovie},
+    discord::{DiscordWebhookBody, RenderStyle},
+    radarr::{RadarrEventType, RadarrGroupKey, RadarrRequestBody},
+};
+use crate::template::Templates;
+
+pub struct Radarr;
+
+impl Source for Radarr {
+    type Event = RadarrRequestBody;
+    type GroupKey = RadarrGroupKey;
+
+    const NAME: &'static str = "radarr";
+    const USER_AGENT: &'static str = "Radarr";
+
+    fn parse(body: Value) -> Result<Self::Event, SourceError> {
+        let mut radarr_request: RadarrRequestBody =
+            serde_json::from_value(body).map_err(SourceError::InvalidPayload)?;
+
+        let event_type = match radarr_request.event_type {
+            Some(RadarrEventType::Download) if radarr_request.is_upgrade.unwrap_or(false) => {
+                RadarrEventType::Upgrade
+            }
+            Some(event_type) => event_type,
+            None => return Err(SourceError::UnsupportedEventType),
+        };
+        radarr_request.event_type = Some(event_type);
+
+        match event_type {
+            // sent when the webhook is saved to check the connection
+            RadarrEventType::Test
+            | RadarrEventType::Grab
+            | RadarrEventType::Download
+            | RadarrEventType::Upgrade => Ok(radarr_request),
+            _ => Err(SourceError::UnsupportedEventType),
+        }
+    }
+
+    fn group_key(event: &Self::Event) -> Self::GroupKey {
+        RadarrGroupKey::from(event)
+    }
+
+    fn event_type(event: &Self::Event) -> String {
+        format!("{:?}", event.event_type.unwrap_or(RadarrEventType::Test))
+    }
+
+    // movies and tracks aren't numbered in a way that collapses into ranges, so every style renders the same
+    // templates are only supported for Sonarr so far
+    fn render(
+        group: Vec<Self::Event>,
+        _style: RenderStyle,
+        _templates: Option<&Templates>,
+    ) -> DiscordWebhookBody {
+        DiscordWebhookBody::from(group)
+    }
+
+    fn describe(event: &Self::Event) -> String {
+        format!(
+            "{:?} Movie: {}",
+            event.event_type.unwrap_or(RadarrEventType::Test),
+            event.movie.title
+        )
+    }
+
+    fn instance(event: &Self::Event) -> Option<String> {
+        event.instance_name.clone()
+    }
+
+    fn details(group: &[Self::Event]) -> BatchDetails {
+        let mut movies: Vec<BatchMovie> = Vec::new();
+        for request in group {
+            let quality = request
+                .movie_file
+                .as_ref()
+                .map(|movie_file| movie_file.quality.clone())
+                .or_else(|| request.release.as_ref()?.quality.clone());
+            let movie = &request.movie;
+            match movies.iter_mut().find(|batch_movie| {
+                batch_movie.id == movie.id
+                    && batch_movie.title == movie.title
+                    && batch_movie.quality == quality
+            }) {
+                Some(batch_movie) => batch_movie.count += 1,
+                None => movies.push(BatchMovie {
+                    id: movie.id,
+                    title: movie.title.clone(),
+                    year: movie.year,
+                    tmdb_id: movie.tmdb_id,
+                    imdb_id: movie.imdb_id.clone(),
+                    quality,
+                    count: 1,
+                }),
+            }
+        }
+        movies.sort_by(|a, b| a.title.cmp(&b.title));
+
+        BatchDetails::Movies { movies }
+    }
+
+    fn download(event: &Self::Event) -> Option<(String, usize)> {
+        let download_id = event.download_id.clone().filter(|id| !id.is_empty())?;
+        Some((download_id, 1))
+    }
+
+    // the connection test is answered right away, every other event type parse accepts is buffered
+    fn delivery(event: &Self::Event) -> Delivery {
+        match event.event_type {
+            Some(RadarrEventType::Test) => Delivery::Test,
+            _ => Delivery::Buffered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::{group_events, group_id};
+
+    fn radarr_body(event_type: &str, is_upgrade: bool, title: &str) -> Value {
+        json!({
+            "eventType": event_type,
+            "isUpgrade": is_upgrade,
+            "movie": { "id": 1, "title": title, "year": 2024 },
+            "movieFile": { "quality": "Bluray-1080p" }
+        })
+    }
+
+    #[test]
+    fn parse_marks_upgrades() {
+        let event = Radarr::parse(radarr_body("Download", true, "Fake Movie")).unwrap();
+        assert_eq!(event.event_type, Some(RadarrEventType::Upgrade));
+        assert_eq!(Radarr::delivery(&event), Delivery::Buffered);
+
+        let event = Radarr::parse(radarr_body("Download", false, "Fake Movie")).unwrap();
+        assert_eq!(event.event_type, Some(RadarrEventType::Download));
+    }
+
+    #[test]
+    fn parse_answers_connection_tests() {
+        let test = Radarr::parse(json!({
+            "eventType": "Test",
+            "instanceName": "Radarr",
+            "movie": { "id": 1, "title": "Test Title", "year": 1970 }
+        }))
+        .unwrap();
+        assert_eq!(Radarr::delivery(&test), Delivery::Test);
+
+        assert!(matches!(
+            Radarr::parse(radarr_body("MovieAdded", false, "Fake Movie")),
+            Err(SourceError::UnsupportedEventType)
+        ));
+        assert!(matches!(
+            Radarr::parse(json!({ "movie": { "title": "Fake Movie" } })),
+            Err(SourceError::UnsupportedEventType)
+        ));
+    }
+
+    #[test]
+    fn groups_by_event_type() {
+        let queue = vec![
+            Radarr::parse(radarr_body("Download", false, "Fake Movie")).unwrap(),
+            Radarr::parse(radarr_body("Download", false, "Other Movie")).unwrap(),
+            Radarr::parse(radarr_body("Download", true, "Fake Movie")).unwrap(),
+            Radarr::parse(radarr_body("Grab", false, "Fake Movie")).unwrap(),
+        ];
+
+        let groups = group_events::<Radarr>(queue);
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&RadarrGroupKey(RadarrEventType::Download)].len(), 2);
+        assert_eq!(groups[&RadarrGroupKey(RadarrEventType::Upgrade)].len(), 1);
+        assert_ne!(
+            group_id::<Radarr>(&RadarrGroupKey(RadarrEventType::Download)),
+            group_id::<Radarr>(&RadarrGroupKey(RadarrEventType::Upgrade))
+        );
+    }
+
+    #[test]
+    fn renders_movies() {
+        let body = Radarr::render(
+            vec![Radarr::parse(radarr_body("Download", false, "Fake Movie")).unwrap()],
+            RenderStyle::Full,
+            None,
+        );
+        assert_eq!(body.content, "Imported: Fake Movie (2024)");
+        assert_eq!(body.embeds[0].title.as_deref(), Some("Fake Movie (2024)"));
+        assert_eq!(
+            body.embeds[0].description.as_deref(),
+            Some("Fake Movie (2024) [Bluray-1080p]")
+        );
+
+        let group = vec![
+            Radarr::parse(radarr_body("Upgrade", false, "Other Movie")).unwrap(),
+            Radarr::parse(radarr_body("Upgrade", false, "Fake Movie")).unwrap(),
+            Radarr::parse(radarr_body("Upgrade", false, "Fake Movie")).unwrap(),
+        ];
+        let body = Radarr::render(group, RenderStyle::Full, None);
+        assert_eq!(body.content, "Upgraded: 2 Movies");
+        assert_eq!(body.embeds[0].title.as_deref(), Some("Movies"));
+        assert_eq!(body.embeds[0].color, Some(0x3E6800));
+        assert_eq!(
+            body.message_lines(),
+            vec![
+                "Fake Movie (2024) [Bluray-1080p] (2x)",
+                "Other Movie (2024) [Bluray-1080p]"
+            ]
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use serde_json::Value;
+
+use super::{Delivery, Source, SourceError};
+use crate::structs::{
+    batch::{BatchDetails, BatchEpisode, BatchSeries},
+    discord::{DiscordWebhookBody, RenderStyle},
+    sonarr::{SonarrEventType, SonarrGroupKey, SonarrRequestBody},
+};
+use crate::template::Templates;
+
+pub struct Sonarr;
+
+impl Source for Sonarr {
+    type Event = SonarrRequestBody;
+    type GroupKey = SonarrGroupKey;
+
+    const NAME: &'static str = "sonarr";
+    const USER_AGENT: &'static str = "Sonarr";
+
+    fn parse(body: Value) -> Result<Self::Event, SourceError> {
+        let mut sonarr_request: SonarrRequestBody =
+            serde_json::from_value(body).map_err(SourceError::InvalidPayload)?;
+
+        // if the event type is Download, check if it's an upgrade and change the event type to Upgrade if it is
+        let event_type = match sonarr_request.event_type {
+            Some(SonarrEventType::Download) if sonarr_request.is_upgrade.unwrap_or(false) => {
+                SonarrEventType::Upgrade
+            }
+            Some(event_type) => event_type,
+            None => return Err(SourceError::UnsupportedEventType),
+        };
+        sonarr_request.event_type = Some(event_type);
+
+        Ok(sonarr_request)
+    }
+
+    fn group_key(event: &Self::Event) -> Self::GroupKey {
+        SonarrGroupKey::from(event)
+    }
+
+    fn event_type(event: &Self::Event) -> String {
+        format!("{:?}", event.event_type.unwrap_or(SonarrEventType::Test))
+    }
+
+    fn render(
+        group: Vec<Self::Event>,
+        style: RenderStyle,
+        templates: Option<&Templates>,
+    ) -> DiscordWebhookBody {
+        DiscordWebhookBody::from_sonarr(&group, style, templates)
+    }
+
+    fn describe(event: &Self::Event) -> String {
+        let event_type = event.event_type.unwrap_or(SonarrEventType::Test);
+        if Self::delivery(event) == Delivery::Immediate {
+            return format!(
+                "{:?}: {}",
+                event_type,
+                event.message.as_deref().unwrap_or(&event.series.title)
+            );
+        }
+        let episodes = event
+            .episodes
+            .iter()
+            .map(|episode| format!("{:02}x{:02}", episode.season_number, episode.episode_number))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "{:?} Episode: {} - {}",
+            event_type, event.series.title, episodes
+        )
+    }
+
+    fn instance(event: &Self::Event) -> Option<String> {
+        event.instance_name.clone()
+    }
+
+    fn details(group: &[Self::Event]) -> BatchDetails {
+        let series = &group[0].series;
+        let SonarrGroupKey(_, _, season) = SonarrGroupKey::from(&group[0]);
+
+        let mut episodes: Vec<BatchEpisode> = Vec::new();
+        for request in group {
+            let quality = request
+                .episode_file
+                .as_ref()
+                .map(|episode_file| episode_file.quality.clone())
+                .or_else(|| request.release.as_ref()?.quality.clone());
+            for episode in &request.episodes {
+                // the same episode grabbed or imported more than once is counted instead of repeated
+                match episodes.iter_mut().find(|batch_episode| {
+                    batch_episode.season == episode.season_number
+                        && batch_episode.episode == episode.episode_number
+                        && batch_episode.quality == quality
+                }) {
+                    Some(batch_episode) => batch_episode.count += 1,
+                    None => episodes.push(BatchEpisode {
+                        season: episode.season_number,
+                        episode: episode.episode_number,
+                        title: episode.title.clone(),
+                        quality: quality.clone(),
+                        count: 1,
+                    }),
+                }
+            }
+        }
+        episodes.sort_by_key(|episode| (episode.season, episode.episode));
+
+        BatchDetails::Episodes {
+            series: BatchSeries {
+                id: series.id,
+                title: series.title.clone(),
+                year: series.year,
+                tvdb_id: series.tvdb_id,
+                imdb_id: series.imdb_id.clone(),
+            },
+            season,
+            episodes,
+        }
+    }
+
+    fn download(event: &Self::Event) -> Option<(String, usize)> {
+        let download_id = event.download_id.clone().filter(|id| !id.is_empty())?;
+        Some((download_id, event.episodes.len()))
+    }
+
+    fn delivery(event: &Self::Event) -> Delivery {
+        match event.event_type {
+            Some(SonarrEventType::Test) => Delivery::Test,
+            // alerts are about something happening right now, they shouldn't wait for a timer
+            Some(
+                SonarrEventType::Health
+                | SonarrEventType::HealthRestored
+                | SonarrEventType::ApplicationUpdate
+                | SonarrEventType::ManualInteractionRequired,
+            ) => Delivery::Immediate,
+            _ => Delivery::Buffered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::{batch_document, group_events, group_id};
+
+    fn sonarr_body(event_type: &str, is_upgrade: bool, season_number: u64) -> Value {
+        json!({
+            "eventType": event_type,
+            "isUpgrade": is_upgrade,
+            "series": { "id": 1, "title": "Fake Series" },
+            "episodes": [{
+                "episodeNumber": 1,
+                "seasonNumber": season_number,
+                "seriesId": 1,
+                "title": "Fake Episode"
+            }]
+        })
+    }
+
+    #[test]
+    fn parse_marks_upgrades() {
+        let event = Sonarr::parse(sonarr_body("Download", true, 1)).unwrap();
+        assert_eq!(event.event_type, Some(SonarrEventType::Upgrade));
+    }
+
+    #[test]
+    fn parse_accepts_every_event_type() {
+        let rename = Sonarr::parse(json!({
+            "eventType": "Rename",
+            "series": { "id": 1, "title": "Fake Series" },
+            "renamedEpisodeFiles": [{
+                "previousRelativePath": "Season 1/old.mkv",
+                "relativePath": "Season 1/new.mkv"
+            }]
+        }))
+        .unwrap();
+        assert_eq!(Sonarr::delivery(&rename), Delivery::Buffered);
+
+        let health = Sonarr::parse(json!({
+            "eventType": "Health",
+            "level": "warning",
+            "message": "Indexers unavailable",
+            "type": "IndexerStatusCheck"
+        }))
+        .unwrap();
+        assert_eq!(Sonarr::delivery(&health), Delivery::Immediate);
+
+        let test = Sonarr::parse(sonarr_body("Test", false, 1)).unwrap();
+        assert_eq!(Sonarr::delivery(&test), Delivery::Test);
+
+        assert!(matches!(
+            Sonarr::parse(json!({ "series": { "title": "Fake Series" } })),
+            Err(SourceError::UnsupportedEventType)
+        ));
+    }
+
+    #[test]
+    fn groups_by_season_and_event_type() {
+        let queue = vec![
+            Sonarr::parse(sonarr_body("Download", false, 1)).unwrap(),
+            Sonarr::parse(sonarr_body("Download", false, 1)).unwrap(),
+            Sonarr::parse(sonarr_body("Download", false, 2)).unwrap(),
+            Sonarr::parse(sonarr_body("Download", true, 2)).unwrap(),
+            Sonarr::parse(sonarr_body("Grab", false, 2)).unwrap(),
+        ];
+
+        let groups = group_events::<Sonarr>(queue);
+
+        assert_eq!(groups.len(), 4);
+        assert_eq!(
+            groups[&SonarrGroupKey(1, SonarrEventType::Download, 1)].len(),
+            2
+        );
+    }
+
+    #[test]
+    fn group_ids_are_stable() {
+        // stored in journals and used as Matrix transaction ids, so this must never change
+        assert_eq!(
+            group_id::<Sonarr>(&SonarrGroupKey(1, SonarrEventType::Download, 1)),
+            "sonarr-1738821789517853511"
+        );
+    }
+
+    #[test]
+    fn batch_document_schema() {
+        let group = vec![
+            Sonarr::parse(sonarr_body("Download", false, 1)).unwrap(),
+            Sonarr::parse(sonarr_body("Download", false, 1)).unwrap(),
+        ];
+
+        let document =
+            serde_json::to_value(batch_document::<Sonarr>(&group, "batch".to_string())).unwrap();
+
+        assert_eq!(document["version"], 1);
+        assert_eq!(document["source"], "sonarr");
+        assert_eq!(document["event_type"], "Download");
+        assert_eq!(document["count"], 2);
+        assert_eq!(document["series"]["title"], "Fake Series");
+        assert_eq!(document["season"], 1);
+        assert_eq!(
+            document["episodes"],
+            json!([{
+                "season": 1,
+                "episode": 1,
+                "title": "Fake Episode",
+                "quality": null,
+                "count": 2
+            }])
+        );
+    }
+
+    #[test]
+    fn renders_templates() {
+        let group = vec![Sonarr::parse(sonarr_body("Download", false, 1)).unwrap()];
+        let templates = Templates {
+            content: Some("{{ action }} {{ series.title }} S{{ season }}".to_string()),
+            line: Some(
+                "{{ '%02d'|format(episode.episodeNumber) }} {{ episode.title }} ({{ quality }})"
+                    .to_string(),
+            ),
+            footer: Some("{{ events[0].eventType }}".to_string()),
+            color: Some("{{ default }}".to_string()),
+            ..Default::default()
+        };
+
+        let body = Sonarr::render(group, RenderStyle::Full, Some(&templates));
+
+        assert_eq!(body.content, "Imported Fake Series S1");
+        let embed = &body.embeds[0];
+        assert_eq!(embed.title.as_deref(), Some("Fake Series"));
+        assert_eq!(embed.description.as_deref(), Some("01 Fake Episode (None)"));
+        assert_eq!(embed.footer.as_ref().unwrap().text, "Download");
+        assert_eq!(embed.color, Some(0x29A44C));
+    }
+
+    #[test]
+    fn rich_embed() {
+        let mut body = sonarr_body("Download", false, 1);
+        body["applicationUrl"] = json!("http://sonarr.local/");
+        body["instanceName"] = json!("Sonarr");
+        body["downloadClient"] = json!("qBittorrent");
+        body["series"]["titleSlug"] = json!("fake-series");
+        body["series"]["images"] = json!([{
+            "coverType": "poster",
+            "url": "/MediaCover/1/poster.jpg",
+            "remoteUrl": "https://artworks.thetvdb.com/poster.jpg"
+        }]);
+        body["episodeFile"] = json!({
+            "quality": "WEBDL-1080p",
+            "releaseGroup": "FAKE",
+            "size": 1_572_864_000u64,
+            "dateAdded": "2024-01-02T03:04:05.1234567Z"
+        });
+        let group = vec![Sonarr::parse(body).unwrap()];
+
+        let embed = Sonarr::render(group, RenderStyle::Full, None)
+            .embeds
+            .remove(0);
+
+        assert_eq!(
+            embed.url.as_deref(),
+            Some("http://sonarr.local/series/fake-series")
+        );
+        assert_eq!(
+            embed.thumbnail.unwrap().url,
+            "https://artworks.thetvdb.com/poster.jpg"
+        );
+        assert_eq!(embed.footer.unwrap().text, "Sonarr · qBittorrent");
+        assert_eq!(embed.timestamp.unwrap().as_secs(), 1_704_164_645);
+        assert_eq!(embed.fields[0].value, "1.46 GiB");
+        assert_eq!(embed.fields[1].value, "FAKE");
+    }
+
+    #[test]
+    fn renders_other_event_types() {
+        let rename = Sonarr::parse(json!({
+            "eventType": "Rename",
+            "series": { "id": 1, "title": "Fake Series" },
+            "renamedEpisodeFiles": [
+                { "previousRelativePath": "a.mkv", "relativePath": "b.mkv" },
+                { "previousRelativePath": "c.mkv", "relativePath": "d.mkv" }
+            ]
+        }))
+        .unwrap();
+        let body = Sonarr::render(vec![rename], RenderStyle::Full, None);
+        assert_eq!(body.content, "Renamed: Fake Series - 2 files");
+        assert_eq!(body.message_lines(), vec!["a.mkv → b.mkv", "c.mkv → d.mkv"]);
+
+        let health = Sonarr::parse(json!({
+            "eventType": "Health",
+            "instanceName": "Sonarr 4K",
+            "level": "error",
+            "message": "No indexers available",
+            "wikiUrl": "https://wiki.servarr.com/sonarr/system"
+        }))
+        .unwrap();
+        let body = Sonarr::render(vec![health], RenderStyle::Full, None);
+        assert_eq!(body.content, "Sonarr 4K: Health Error");
+        assert_eq!(body.embeds[0].color, Some(0xE74C3C));
+        assert_eq!(body.message_lines(), vec!["No indexers available"]);
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+// version of the BatchDocument schema, bumped whenever a field is removed or changes meaning
+pub const BATCH_DOCUMENT_VERSION: u32 = 1;
+
+// the raw contents of a flushed group, sent as-is by the JSON destination
+// this is a stable schema for automation to consume, see the README for an example
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchDocument {
+    pub version: u32,
+    // the application the events came from, ex: sonarr
+    pub source: String,
+    // instanceName from the payload, if the application sent one
+    pub instance: Option<String>,
+    // stays the same for every batch of the same group (same series, season and event type for Sonarr)
+    pub group_key: String,
+    // unique to this batch, stays the same across retries
+    pub batch_id: String,
+    // ex: Grab, Download, Upgrade
+    pub event_type: String,
+    // number of webhooks that were buffered into this batch
+    pub count: usize,
+    #[serde(flatten)]
+    pub details: BatchDetails,
+}
+
+// source specific contents of a batch
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum BatchDetails {
+    Episodes {
+        series: BatchSeries,
+        season: u64,
+        episodes: Vec<BatchEpisode>,
+    },
+    Movies {
+        movies: Vec<BatchMovie>,
+    },
+    Tracks {
+        artist: BatchArtist,
+        album: Option<BatchAlbum>,
+        tracks: Vec<BatchTrack>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchSeries {
+    pub id: Option<u64>,
+    pub title: String,
+    pub year: Option<u64>,
+    pub tvdb_id: Option<u64>,
+    pub imdb_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchEpisode {
+    pub season: u64,
+    pub episode: u64,
+    pub title: String,
+    pub quality: Option<String>,
+    // how many times this episode showed up in the batch
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchMovie {
+    pub id: Option<u64>,
+    pub title: String,
+    pub year: Option<u64>,
+    pub tmdb_id: Option<u64>,
+    pub imdb_id: Option<String>,
+    pub quality: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchArtist {
+    pub id: Option<u64>,
+    pub name: String,
+    pub mb_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchAlbum {
+    pub id: Option<u64>,
+    pub title: String,
+    pub mb_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchTrack {
+    pub number: String,
+    pub title: String,
+    pub quality: Option<String>,
+    pub count: u64,
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use super::discord::DiscordWebhook;
+use crate::send::SendError;
+
+// a webhook that couldn't be delivered, kept so it can be looked at, retried or deleted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    pub id: u64,
+    // when the last attempt failed, in milliseconds since the epoch
+    pub failed_at: u64,
+    pub webhook: DiscordWebhook,
+    // what the destination responded with, if it's known
+    pub status: Option<u16>,
+    pub error: String,
+    pub attempts: u32,
+}
+
+impl DeadLetter {
+    // describe a webhook send_webhook gave up on, attempts counts every try so far including earlier ones
+    // the webhook keeps track of the parts that were delivered so retrying it doesn't send them again
+    pub fn failed(
+        id: u64,
+        failed_at: u64,
+        mut webhook: DiscordWebhook,
+        error: &SendError,
+        attempts: u32,
+    ) -> Self {
+        webhook.sent_parts = webhook.sent_parts.max(error.sent_parts);
+        DeadLetter {
+            id,
+            failed_at,
+            webhook,
+            status: error.status.map(|status| status.as_u16()),
+            error: error.error.clone(),
+            attempts,
+        }
+    }
+    // the dead letter as the admin API shows it, without the tokens in its URL and destination
+    pub fn redacted(&self) -> Self {
+        let mut letter = self.clone();
+        letter.webhook.url = letter.webhook.destination.redact_url(&letter.webhook.url);
+        letter.webhook.destination = letter.webhook.destination.redacted();
+        letter
+    }
+}
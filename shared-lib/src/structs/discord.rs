@@ -1,33 +1,349 @@
 use serde::{Deserialize, Serialize};
+use twilight_model::channel::message::embed::{EmbedField, EmbedFooter, EmbedThumbnail};
 use twilight_model::channel::message::Embed;
+use twilight_model::util::Timestamp;
 
+use crate::destination::Destination;
+use crate::template::{self, Templates};
+
+use super::batch::BatchDocument;
+
+use super::lidarr::{LidarrEventType, LidarrRequestBody};
+use super::radarr::{RadarrEventType, RadarrRequestBody};
 use super::sonarr::{SonarrEventType, SonarrRequestBody};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DiscordWebhook {
     pub url: String,
     pub body: DiscordWebhookBody,
+    // messages queued before destinations existed won't have this set, so they default to Discord
+    #[serde(default)]
+    pub destination: Destination,
+    // identifies the flushed group this webhook was rendered from
+    // it stays the same across retries so destinations that support it can deduplicate
+    #[serde(default)]
+    pub batch_id: String,
+    // event type of the group, ex: Grab, Download, Upgrade
+    #[serde(default)]
+    pub event_type: String,
+    // the raw group, only set for JSON destinations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<BatchDocument>,
+    // when set, the Discord message with this id is edited instead of posting a new one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    // how many messages of a batch split over several were delivered before a later one failed,
+    // resending the webhook only sends the rest
+    #[serde(default)]
+    pub sent_parts: usize,
 }
 
 impl DiscordWebhook {
-    pub fn new(url: String, body: DiscordWebhookBody) -> Self {
-        DiscordWebhook { url, body }
+    pub fn new(
+        url: String,
+        body: DiscordWebhookBody,
+        destination: Destination,
+        batch_id: String,
+        event_type: String,
+    ) -> Self {
+        DiscordWebhook {
+            url,
+            body,
+            destination,
+            batch_id,
+            event_type,
+            document: None,
+            message_id: None,
+            sent_parts: 0,
+        }
     }
 }
 
+// Discord rejects embeds with descriptions over 4096 characters or field values over 1024, messages with more than 10 embeds,
+// messages whose embeds add up to more than 6000 characters and content over 2000 characters
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+const MAX_FIELD_VALUE_LENGTH: usize = 1024;
+const MAX_EMBEDS: usize = 10;
+const MAX_EMBEDS_LENGTH: usize = 6000;
+const MAX_CONTENT_LENGTH: usize = 2000;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DiscordWebhookBody {
     pub content: String,
     pub embeds: Vec<Embed>,
 }
 
+impl DiscordWebhookBody {
+    // the description lines of every embed, for destinations that only take a plain message
+    pub fn message_lines(&self) -> Vec<String> {
+        self.embeds
+            .iter()
+            .filter_map(|embed| embed.description.as_deref())
+            .flat_map(str::lines)
+            .map(str::to_string)
+            .collect()
+    }
+
+    // a grab message updated with how many of its items have been imported so far, turns green once they all are
+    pub fn with_progress(&self, imported: usize, total: usize) -> DiscordWebhookBody {
+        let mut body = self.clone();
+        body.content = content_with_suffix(
+            &self.content,
+            &format!(" ({}/{} imported)", imported, total),
+        );
+        if imported >= total {
+            for embed in &mut body.embeds {
+                embed.color = Some(0x29A44C);
+            }
+        }
+        body
+    }
+
+    // split the body into as many messages as it takes to fit within Discord's limits
+    // descriptions are only split between lines, and each message is marked with (part 2/3) when there's more than one
+    // field values and content that are too long on their own are cut short
+    pub fn paginate(self) -> Vec<DiscordWebhookBody> {
+        let mut embeds = Vec::new();
+        for mut embed in self.embeds {
+            for field in &mut embed.fields {
+                field.value = truncate(&field.value, MAX_FIELD_VALUE_LENGTH);
+            }
+            // the title, author, fields and footer count towards the 6000 characters along with the description
+            let rest_length = embed_length(&Embed {
+                description: None,
+                ..embed.clone()
+            });
+            let max_length = MAX_DESCRIPTION_LENGTH
+                .min(MAX_EMBEDS_LENGTH.saturating_sub(rest_length))
+                .max(1);
+            let chunks = split_lines(embed.description.as_deref().unwrap_or_default(), max_length);
+            if chunks.len() <= 1 {
+                embeds.push(embed);
+                continue;
+            }
+            // fields and the footer summarize the whole embed, so only the last chunk keeps them
+            let last = chunks.len() - 1;
+            embeds.extend(chunks.into_iter().enumerate().map(|(i, chunk)| Embed {
+                description: Some(chunk),
+                fields: match i == last {
+                    true => embed.fields.clone(),
+                    false => Vec::new(),
+                },
+                footer: embed.footer.clone().filter(|_| i == last),
+                ..embed.clone()
+            }));
+        }
+
+        let mut pages: Vec<Vec<Embed>> = Vec::new();
+        let mut page: Vec<Embed> = Vec::new();
+        let mut page_length = 0;
+        for embed in embeds {
+            let length = embed_length(&embed);
+            if !page.is_empty()
+                && (page.len() == MAX_EMBEDS || page_length + length > MAX_EMBEDS_LENGTH)
+            {
+                pages.push(std::mem::take(&mut page));
+                page_length = 0;
+            }
+            page.push(embed);
+            page_length += length;
+        }
+        pages.push(page);
+
+        let page_count = pages.len();
+        if page_count == 1 {
+            return vec![DiscordWebhookBody {
+                content: truncate(&self.content, MAX_CONTENT_LENGTH),
+                embeds: pages.remove(0),
+            }];
+        }
+        pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, embeds)| DiscordWebhookBody {
+                content: content_with_suffix(
+                    &self.content,
+                    &format!(" (part {}/{})", i + 1, page_count),
+                ),
+                embeds,
+            })
+            .collect()
+    }
+}
+
+// the characters of an embed that count towards Discord's 6000 character limit
+fn embed_length(embed: &Embed) -> usize {
+    let length = |text: Option<&str>| text.map_or(0, |text| text.chars().count());
+    length(embed.title.as_deref())
+        + length(embed.description.as_deref())
+        + length(embed.footer.as_ref().map(|footer| footer.text.as_str()))
+        + length(embed.author.as_ref().map(|author| author.name.as_str()))
+        + embed
+            .fields
+            .iter()
+            .map(|field| length(Some(&field.name)) + length(Some(&field.value)))
+            .sum::<usize>()
+}
+
+// text cut short to at most max_length characters, ending in … when it was
+fn truncate(text: &str, max_length: usize) -> String {
+    match text.chars().count() > max_length {
+        true => format!("{}…", text.chars().take(max_length - 1).collect::<String>()),
+        false => text.to_string(),
+    }
+}
+
+// content with something appended, cutting the content short so the suffix still fits
+fn content_with_suffix(content: &str, suffix: &str) -> String {
+    let max_length = MAX_CONTENT_LENGTH - suffix.chars().count();
+    format!("{}{}", truncate(content, max_length), suffix)
+}
+
+// break text into chunks of at most max_length characters without splitting lines
+// a single line that's too long on its own is cut short
+fn split_lines(text: &str, max_length: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_length = 0;
+    for line in text.lines() {
+        let line = truncate(line, max_length);
+        let line_length = line.chars().count();
+        if chunk_length > 0 && chunk_length + line_length + 1 > max_length {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_length = 0;
+        }
+        if chunk_length > 0 {
+            chunk.push('\n');
+            chunk_length += 1;
+        }
+        chunk.push_str(&line);
+        chunk_length += line_length;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+// how much detail to put in rendered messages
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderStyle {
+    // every episode on its own line
+    #[default]
+    Full,
+    // runs of consecutive episodes with the same quality are collapsed into ranges like 01x01–01x12
+    Compact,
+}
+
+impl std::str::FromStr for RenderStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(RenderStyle::Full),
+            "compact" => Ok(RenderStyle::Compact),
+            _ => Err(format!(
+                "unknown render style {}, must be full or compact",
+                s
+            )),
+        }
+    }
+}
+
+// the built in line for a (season, episode, title, quality, count) tuple
+fn default_episode_line(
+    (season_number, episode_number, title, quality, count): &(u64, u64, String, String, u64),
+) -> String {
+    match count {
+        1 => format!(
+            "{:02}x{:02} - {} [{}]",
+            season_number, episode_number, title, quality
+        ),
+        _ => format!(
+            "{:02}x{:02} - {} [{}] ({}x)",
+            season_number, episode_number, title, quality, count
+        ),
+    }
+}
+
+// render sorted (season, episode, title, quality, count) tuples into description lines
+// single renders an episode that gets a line of its own
+fn episode_lines(
+    episodes: Vec<(u64, u64, String, String, u64)>,
+    style: RenderStyle,
+    single: impl Fn(&(u64, u64, String, String, u64)) -> String,
+) -> Vec<String> {
+    if style == RenderStyle::Full {
+        return episodes.iter().map(single).collect();
+    }
+
+    // episodes that showed up more than once keep their own line so the count isn't lost
+    let mut lines = Vec::new();
+    let mut run: Vec<&(u64, u64, String, String, u64)> = Vec::new();
+    let flush = |run: &mut Vec<&(u64, u64, String, String, u64)>, lines: &mut Vec<String>| {
+        match run.as_slice() {
+            [] => {}
+            [episode] => lines.push(single(episode)),
+            [first, .., last] => lines.push(format!(
+                "{:02}x{:02}–{:02}x{:02} [{}]",
+                first.0, first.1, last.0, last.1, first.3
+            )),
+        }
+        run.clear();
+    };
+    for episode in &episodes {
+        let continues_run = run.last().is_some_and(|last| {
+            last.0 == episode.0 && last.1 + 1 == episode.1 && last.3 == episode.3
+        });
+        if episode.4 > 1 || !continues_run {
+            flush(&mut run, &mut lines);
+        }
+        if episode.4 > 1 {
+            lines.push(single(episode));
+        } else {
+            run.push(episode);
+        }
+    }
+    flush(&mut run, &mut lines);
+    lines
+}
+
 impl From<&Vec<SonarrRequestBody>> for DiscordWebhookBody {
     fn from(sonarr_data: &Vec<SonarrRequestBody>) -> Self {
+        DiscordWebhookBody::from_sonarr(sonarr_data, RenderStyle::Full, None)
+    }
+}
+
+impl DiscordWebhookBody {
+    // templates replace the built in content, title, line, footer and color when set
+    pub fn from_sonarr(
+        sonarr_data: &[SonarrRequestBody],
+        style: RenderStyle,
+        templates: Option<&Templates>,
+    ) -> Self {
         let event_type = sonarr_data[0].event_type.as_ref().unwrap();
+        match event_type {
+            SonarrEventType::Rename => return sonarr_renames(sonarr_data),
+            SonarrEventType::SeriesAdd | SonarrEventType::SeriesDelete => {
+                return sonarr_series_events(sonarr_data)
+            }
+            SonarrEventType::Test
+            | SonarrEventType::Health
+            | SonarrEventType::HealthRestored
+            | SonarrEventType::ApplicationUpdate
+            | SonarrEventType::ManualInteractionRequired => return sonarr_alert(&sonarr_data[0]),
+            _ => {}
+        }
+
         let series_title = &sonarr_data[0].series.title;
-        let season_number = sonarr_data[0].episodes[0].season_number;
+        let season_number = sonarr_data[0]
+            .episodes
+            .first()
+            .map_or(0, |episode| episode.season_number);
+        let templates = templates.cloned().unwrap_or_default();
 
-        let content = match event_type {
+        let action = match event_type {
             SonarrEventType::Grab => "Grabbed",
             SonarrEventType::Download => {
                 if sonarr_data[0].is_upgrade.unwrap_or(false) {
@@ -36,20 +352,33 @@ impl From<&Vec<SonarrRequestBody>> for DiscordWebhookBody {
                     "Imported"
                 }
             }
-            SonarrEventType::Rename => "Renamed",
+            SonarrEventType::Upgrade => "Upgraded",
+            SonarrEventType::EpisodeFileDelete => "Deleted",
             _ => "Unknown",
         };
-        let content = match sonarr_data.len() {
-            1 => format!(
+        // every template can use these, fields keep the names Sonarr sends them with
+        let context = serde_json::json!({
+            "action": action,
+            "event_type": event_type,
+            "series": sonarr_data[0].series,
+            "season": season_number,
+            "count": sonarr_data.len(),
+            "events": sonarr_data,
+        });
+
+        let content = match (sonarr_data.len(), sonarr_data[0].episodes.first()) {
+            (1, Some(episode)) => format!(
                 "{}: {} - {:02}x{:02} - {}",
-                content,
-                series_title,
-                season_number,
-                sonarr_data[0].episodes[0].episode_number,
-                sonarr_data[0].episodes[0].title
+                action, series_title, season_number, episode.episode_number, episode.title
             ),
-            _ => format!("{}: {} Season {:02}", content, series_title, season_number),
+            _ => format!("{}: {} Season {:02}", action, series_title, season_number),
         };
+        let content = template::render_or(
+            "content",
+            templates.content.as_deref(),
+            context.clone(),
+            content,
+        );
 
         let mut episodes_with_quality: Vec<_> = sonarr_data
             .iter()
@@ -89,34 +418,398 @@ impl From<&Vec<SonarrRequestBody>> for DiscordWebhookBody {
                 .then(a.3.cmp(&b.3))
         });
 
-        let description = episodes_with_quality
-            .into_iter()
-            .map(
-                |(season_number, episode_number, title, quality, count)| match count {
-                    1 => format!(
-                        "{:02}x{:02} - {} [{}]",
-                        season_number, episode_number, title, quality
-                    ),
-                    _ => format!(
-                        "{:02}x{:02} - {} [{}] ({}x)",
-                        season_number, episode_number, title, quality, count
-                    ),
+        let line = |episode_line: &(u64, u64, String, String, u64)| {
+            let default = default_episode_line(episode_line);
+            let (season_number, episode_number, _, quality, count) = episode_line;
+            let Some((request, episode)) = sonarr_data.iter().find_map(|request| {
+                request
+                    .episodes
+                    .iter()
+                    .find(|episode| {
+                        episode.season_number == *season_number
+                            && episode.episode_number == *episode_number
+                    })
+                    .map(|episode| (request, episode))
+            }) else {
+                return default;
+            };
+            let context = serde_json::json!({
+                "event": request,
+                "series": request.series,
+                "episode": episode,
+                "episode_file": request.episode_file,
+                "release": request.release,
+                "quality": quality,
+                "count": count,
+            });
+            template::render_or("line", templates.line.as_deref(), context, default)
+        };
+        let description = episode_lines(episodes_with_quality, style, line).join("\n");
+
+        let color = match event_type {
+            SonarrEventType::Test => 0x0800FF,              // blue
+            SonarrEventType::Grab => 0xFFC130,              // yellow
+            SonarrEventType::Download => 0x29A44C,          // green
+            SonarrEventType::Upgrade => 0x3E6800,           // dark green
+            SonarrEventType::Rename => 0xFF00FF,            // purple
+            SonarrEventType::EpisodeFileDelete => 0xE74C3C, // red
+            _ => 0xFFFFFF,
+        };
+        let color = template::parse_color(&template::render_or(
+            "color",
+            templates.color.as_deref(),
+            context.clone(),
+            format!("#{:06X}", color),
+        ))
+        .unwrap_or(color);
+
+        let title = template::render_or(
+            "title",
+            templates.title.as_deref(),
+            context.clone(),
+            series_title.to_string(),
+        );
+
+        // names the instance and download clients, ex: "Sonarr · qBittorrent"
+        let mut download_clients: Vec<&str> = sonarr_data
+            .iter()
+            .filter_map(|request| request.download_client.as_deref())
+            .filter(|client| !client.is_empty())
+            .collect();
+        download_clients.sort();
+        download_clients.dedup();
+        let footer = sonarr_data[0]
+            .instance_name
+            .iter()
+            .map(String::as_str)
+            .filter(|instance| !instance.is_empty())
+            .chain(download_clients)
+            .collect::<Vec<_>>()
+            .join(" · ");
+        let footer = template::render_or("footer", templates.footer.as_deref(), context, footer);
+
+        // the newest file in the group, grabs don't have one
+        let timestamp = sonarr_data
+            .iter()
+            .filter_map(|request| request.episode_file.as_ref()?.date_added.as_deref())
+            .filter_map(|date_added| Timestamp::parse(date_added).ok())
+            .max_by_key(|timestamp| timestamp.as_micros());
+
+        let mut fields = Vec::new();
+        let mut delete_reasons: Vec<&str> = sonarr_data
+            .iter()
+            .filter_map(|request| request.delete_reason.as_deref())
+            .collect();
+        delete_reasons.sort();
+        delete_reasons.dedup();
+        if !delete_reasons.is_empty() {
+            fields.push(EmbedField {
+                inline: true,
+                name: "Reason".to_string(),
+                value: delete_reasons.join(", "),
+            });
+        }
+        let size: u64 = sonarr_data.iter().filter_map(SonarrRequestBody::size).sum();
+        if size > 0 {
+            fields.push(EmbedField {
+                inline: true,
+                name: "Size".to_string(),
+                value: format_size(size),
+            });
+        }
+        let mut release_groups: Vec<&str> = sonarr_data
+            .iter()
+            .filter_map(SonarrRequestBody::release_group)
+            .collect();
+        release_groups.sort();
+        release_groups.dedup();
+        if !release_groups.is_empty() {
+            fields.push(EmbedField {
+                inline: true,
+                name: match release_groups.len() {
+                    1 => "Release Group".to_string(),
+                    _ => "Release Groups".to_string(),
                 },
+                value: release_groups.join(", "),
+            });
+        }
+
+        let embed = Embed {
+            title: Some(title),
+            color: Some(color),
+            fields,
+            kind: "rich".to_string(),
+            author: None,
+            description: Some(description),
+            footer: (!footer.is_empty()).then_some(EmbedFooter {
+                icon_url: None,
+                proxy_icon_url: None,
+                text: footer,
+            }),
+            image: None,
+            provider: None,
+            thumbnail: sonarr_data[0]
+                .series
+                .poster_url()
+                .map(|url| EmbedThumbnail {
+                    height: None,
+                    proxy_url: None,
+                    url: url.to_string(),
+                    width: None,
+                }),
+            timestamp,
+            url: sonarr_data[0].series_url(),
+            video: None,
+        };
+
+        DiscordWebhookBody {
+            content,
+            embeds: vec![embed],
+        }
+    }
+}
+
+// a plain embed with just a title, description and color
+fn simple_embed(title: String, description: String, color: u32) -> Embed {
+    Embed {
+        title: Some(title),
+        color: Some(color),
+        fields: Vec::new(),
+        kind: "rich".to_string(),
+        author: None,
+        description: (!description.is_empty()).then_some(description),
+        footer: None,
+        image: None,
+        provider: None,
+        thumbnail: None,
+        timestamp: None,
+        url: None,
+        video: None,
+    }
+}
+
+// renames of a series' files, one line per file
+fn sonarr_renames(sonarr_data: &[SonarrRequestBody]) -> DiscordWebhookBody {
+    let series = &sonarr_data[0].series;
+    let lines: Vec<String> = sonarr_data
+        .iter()
+        .flat_map(|request| request.renamed_episode_files.iter().flatten())
+        .map(|file| {
+            format!(
+                "{} → {}",
+                file.previous_relative_path.as_deref().unwrap_or("?"),
+                file.relative_path.as_deref().unwrap_or("?")
             )
+        })
+        .collect();
+
+    let content = match lines.len() {
+        1 => format!("Renamed: {} - 1 file", series.title),
+        count => format!("Renamed: {} - {} files", series.title, count),
+    };
+    let mut embed = simple_embed(series.title.clone(), lines.join("\n"), 0xFF00FF); // purple
+    embed.url = sonarr_data[0].series_url();
+    DiscordWebhookBody {
+        content,
+        embeds: vec![embed],
+    }
+}
+
+// series added to or removed from Sonarr, grouped by series
+fn sonarr_series_events(sonarr_data: &[SonarrRequestBody]) -> DiscordWebhookBody {
+    let request = &sonarr_data[0];
+    let series = &request.series;
+    let title = match series.year {
+        Some(year) => format!("{} ({})", series.title, year),
+        None => series.title.clone(),
+    };
+
+    let (content, description, color) = match request.event_type {
+        Some(SonarrEventType::SeriesDelete) => (
+            format!("Series Deleted: {}", title),
+            match request.deleted_files {
+                Some(true) => "Files were deleted".to_string(),
+                _ => "Files were kept".to_string(),
+            },
+            0xE74C3C, // red
+        ),
+        _ => (
+            format!("Series Added: {}", title),
+            series.path.clone().unwrap_or_default(),
+            0x0800FF, // blue
+        ),
+    };
+    let mut embed = simple_embed(title, description, color);
+    embed.url = request.series_url();
+    embed.thumbnail = series.poster_url().map(|url| EmbedThumbnail {
+        height: None,
+        proxy_url: None,
+        url: url.to_string(),
+        width: None,
+    });
+    DiscordWebhookBody {
+        content,
+        embeds: vec![embed],
+    }
+}
+
+// a single event that's sent right away instead of being batched, like a health check failing
+fn sonarr_alert(request: &SonarrRequestBody) -> DiscordWebhookBody {
+    let instance = request.instance_name.as_deref().unwrap_or("Sonarr");
+    let message = request.message.clone().unwrap_or_default();
+
+    let (title, description, color) = match request.event_type {
+        Some(SonarrEventType::Health) => {
+            let level = request.level.as_deref().unwrap_or("warning");
+            let color = match level.to_lowercase().as_str() {
+                "error" => 0xE74C3C, // red
+                _ => 0xFFC130,       // yellow
+            };
+            (format!("Health {}", capitalize(level)), message, color)
+        }
+        Some(SonarrEventType::HealthRestored) => {
+            ("Health Restored".to_string(), message, 0x29A44C) // green
+        }
+        Some(SonarrEventType::ApplicationUpdate) => (
+            "Application Updated".to_string(),
+            format!(
+                "{} → {}",
+                request.previous_version.as_deref().unwrap_or("?"),
+                request.new_version.as_deref().unwrap_or("?")
+            ),
+            0x0800FF, // blue
+        ),
+        Some(SonarrEventType::ManualInteractionRequired) => {
+            let mut lines = Vec::new();
+            if let Some(status) = &request.download_status {
+                lines.push(format!("Status: {}", status));
+            }
+            for status_message in request.download_status_messages.iter().flatten() {
+                let title = status_message.title.as_deref().unwrap_or_default();
+                match status_message.messages.as_slice() {
+                    [] => lines.push(title.to_string()),
+                    messages => lines.push(format!("{}: {}", title, messages.join(", "))),
+                }
+            }
+            (
+                format!("Manual Interaction Required: {}", request.series.title),
+                lines.join("\n"),
+                0xFF8C00, // orange
+            )
+        }
+        _ => ("Test".to_string(), message, 0x0800FF), // blue
+    };
+
+    let mut embed = simple_embed(title.clone(), description, color);
+    embed.url = request.wiki_url.clone().or_else(|| request.series_url());
+    DiscordWebhookBody {
+        content: format!("{}: {}", instance, title),
+        embeds: vec![embed],
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// human readable size in binary units like Sonarr shows them, ex: 1.46 GiB
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.2} {}", size, UNITS[unit]),
+    }
+}
+
+impl From<Vec<SonarrRequestBody>> for DiscordWebhookBody {
+    fn from(sonarr_data: Vec<SonarrRequestBody>) -> Self {
+        DiscordWebhookBody::from(&sonarr_data)
+    }
+}
+
+impl From<&Vec<RadarrRequestBody>> for DiscordWebhookBody {
+    fn from(radarr_data: &Vec<RadarrRequestBody>) -> Self {
+        let event_type = radarr_data[0].event_type.as_ref().unwrap();
+
+        let content = match event_type {
+            RadarrEventType::Grab => "Grabbed",
+            RadarrEventType::Download => {
+                if radarr_data[0].is_upgrade.unwrap_or(false) {
+                    "Upgraded"
+                } else {
+                    "Imported"
+                }
+            }
+            RadarrEventType::Upgrade => "Upgraded",
+            RadarrEventType::Rename => "Renamed",
+            _ => "Unknown",
+        };
+
+        let mut movies_with_quality: Vec<_> = radarr_data
+            .iter()
+            .map(|request| {
+                let quality = request
+                    .movie_file
+                    .as_ref()
+                    .map(|movie_file| movie_file.quality.clone())
+                    .or_else(|| request.release.clone()?.quality)
+                    .unwrap_or_else(|| "None".to_string());
+                let title = match request.movie.year {
+                    Some(year) => format!("{} ({})", request.movie.title, year),
+                    None => request.movie.title.clone(),
+                };
+                (title, quality)
+            })
+            .fold(Vec::new(), |mut acc: Vec<(String, String, u64)>, x| {
+                match acc.iter().position(|(t, _, _)| *t == x.0) {
+                    Some(i) => acc[i].2 += 1,
+                    None => acc.push((x.0, x.1, 1)),
+                };
+                acc
+            });
+        movies_with_quality.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let (content, embed_title) = match movies_with_quality.len() {
+            1 => (
+                format!("{}: {}", content, movies_with_quality[0].0),
+                movies_with_quality[0].0.clone(),
+            ),
+            count => (
+                format!("{}: {} Movies", content, count),
+                "Movies".to_string(),
+            ),
+        };
+
+        let description = movies_with_quality
+            .into_iter()
+            .map(|(title, quality, count)| match count {
+                1 => format!("{} [{}]", title, quality),
+                _ => format!("{} [{}] ({}x)", title, quality, count),
+            })
             .collect::<Vec<_>>()
             .join("\n");
 
         let color = match event_type {
-            SonarrEventType::Test => 0x0800FF,     // blue
-            SonarrEventType::Grab => 0xFFC130,     // yellow
-            SonarrEventType::Download => 0x29A44C, // green
-            SonarrEventType::Upgrade => 0x3E6800,  // dark green
-            SonarrEventType::Rename => 0xFF00FF,   // purple
+            RadarrEventType::Test => 0x0800FF,     // blue
+            RadarrEventType::Grab => 0xFFC130,     // yellow
+            RadarrEventType::Download => 0x29A44C, // green
+            RadarrEventType::Upgrade => 0x3E6800,  // dark green
+            RadarrEventType::Rename => 0xFF00FF,   // purple
             _ => 0xFFFFFF,
         };
 
         let embed = Embed {
-            title: Some(series_title.to_string()),
+            title: Some(embed_title),
             color: Some(color),
             fields: Vec::new(),
             kind: "rich".to_string(),
@@ -138,9 +831,157 @@ impl From<&Vec<SonarrRequestBody>> for DiscordWebhookBody {
     }
 }
 
-impl From<Vec<SonarrRequestBody>> for DiscordWebhookBody {
-    fn from(sonarr_data: Vec<SonarrRequestBody>) -> Self {
-        DiscordWebhookBody::from(&sonarr_data)
+impl From<Vec<RadarrRequestBody>> for DiscordWebhookBody {
+    fn from(radarr_data: Vec<RadarrRequestBody>) -> Self {
+        DiscordWebhookBody::from(&radarr_data)
+    }
+}
+
+impl From<&Vec<LidarrRequestBody>> for DiscordWebhookBody {
+    fn from(lidarr_data: &Vec<LidarrRequestBody>) -> Self {
+        let event_type = lidarr_data[0].event_type.as_ref().unwrap();
+        let artist_name = &lidarr_data[0].artist.name;
+
+        let content = match event_type {
+            LidarrEventType::Grab => "Grabbed",
+            LidarrEventType::Download => {
+                if lidarr_data[0].is_upgrade.unwrap_or(false) {
+                    "Upgraded"
+                } else {
+                    "Imported"
+                }
+            }
+            LidarrEventType::Upgrade => "Upgraded",
+            LidarrEventType::Rename => "Renamed",
+            LidarrEventType::Retag => "Retagged",
+            _ => "Unknown",
+        };
+
+        // (album, track number, track title, quality, count)
+        // grabs don't list tracks yet so those get a single line per album with an empty track title
+        let mut tracks_with_quality: Vec<_> = lidarr_data
+            .iter()
+            .flat_map(|request| {
+                let release_quality = request.release.as_ref().and_then(|r| r.quality.clone());
+                let albums = match (&request.album, &request.albums) {
+                    (Some(album), _) => vec![album.title.clone()],
+                    (None, Some(albums)) => albums.iter().map(|a| a.title.clone()).collect(),
+                    (None, None) => vec!["Unknown Album".to_string()],
+                };
+                match &request.tracks {
+                    Some(tracks) if !tracks.is_empty() => tracks
+                        .iter()
+                        .map(|track| {
+                            let quality = track
+                                .quality
+                                .clone()
+                                .or_else(|| release_quality.clone())
+                                .unwrap_or_else(|| "None".to_string());
+                            (
+                                albums[0].clone(),
+                                track.track_number.clone(),
+                                track.title.clone(),
+                                quality,
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                    _ => albums
+                        .into_iter()
+                        .map(|album| {
+                            let quality = release_quality
+                                .clone()
+                                .unwrap_or_else(|| "None".to_string());
+                            (album, String::new(), String::new(), quality)
+                        })
+                        .collect::<Vec<_>>(),
+                }
+            })
+            .fold(
+                Vec::new(),
+                |mut acc: Vec<(String, String, String, String, u64)>, x| {
+                    match acc
+                        .iter()
+                        .position(|(a, n, t, _, _)| *a == x.0 && *n == x.1 && *t == x.2)
+                    {
+                        Some(i) => acc[i].4 += 1,
+                        None => acc.push((x.0, x.1, x.2, x.3, 1)),
+                    };
+                    acc
+                },
+            );
+        tracks_with_quality.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then(
+                    a.1.parse::<u64>()
+                        .unwrap_or(0)
+                        .cmp(&b.1.parse::<u64>().unwrap_or(0)),
+                )
+                .then(a.1.cmp(&b.1))
+                .then(a.2.cmp(&b.2))
+        });
+
+        let mut albums: Vec<&String> = tracks_with_quality.iter().map(|t| &t.0).collect();
+        albums.dedup();
+        let content = match albums.len() {
+            1 => format!("{}: {} - {}", content, artist_name, albums[0]),
+            count => format!("{}: {} - {} Albums", content, artist_name, count),
+        };
+        let show_album_headers = albums.len() > 1;
+
+        let mut lines = Vec::new();
+        let mut current_album: Option<&String> = None;
+        for (album, track_number, title, quality, count) in &tracks_with_quality {
+            if show_album_headers && current_album != Some(album) {
+                lines.push(format!("**{}**", album));
+                current_album = Some(album);
+            }
+            let line = match track_number.is_empty() && title.is_empty() {
+                true => format!("{} [{}]", album, quality),
+                false => format!("{} - {} [{}]", track_number, title, quality),
+            };
+            lines.push(match count {
+                1 => line,
+                _ => format!("{} ({}x)", line, count),
+            });
+        }
+        let description = lines.join("\n");
+
+        let color = match event_type {
+            LidarrEventType::Test => 0x0800FF,     // blue
+            LidarrEventType::Grab => 0xFFC130,     // yellow
+            LidarrEventType::Download => 0x29A44C, // green
+            LidarrEventType::Upgrade => 0x3E6800,  // dark green
+            LidarrEventType::Rename => 0xFF00FF,   // purple
+            LidarrEventType::Retag => 0xFF00FF,    // purple
+            _ => 0xFFFFFF,
+        };
+
+        let embed = Embed {
+            title: Some(artist_name.to_string()),
+            color: Some(color),
+            fields: Vec::new(),
+            kind: "rich".to_string(),
+            author: None,
+            description: Some(description),
+            footer: None,
+            image: None,
+            provider: None,
+            thumbnail: None,
+            timestamp: None,
+            url: None,
+            video: None,
+        };
+
+        DiscordWebhookBody {
+            content,
+            embeds: vec![embed],
+        }
+    }
+}
+
+impl From<Vec<LidarrRequestBody>> for DiscordWebhookBody {
+    fn from(lidarr_data: Vec<LidarrRequestBody>) -> Self {
+        DiscordWebhookBody::from(&lidarr_data)
     }
 }
 
@@ -519,3 +1360,212 @@ impl From<Vec<SonarrRequestBody>> for DiscordWebhookBody {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use twilight_model::channel::message::embed::EmbedAuthor;
+
+    use super::*;
+
+    fn embed(lines: usize) -> Embed {
+        let description = (1..=lines)
+            .map(|i| format!("01x{:02} - Fake Episode {} [WEBDL-1080p]", i % 100, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Embed {
+            title: Some("Fake Series".to_string()),
+            color: Some(0x29A44C),
+            fields: Vec::new(),
+            kind: "rich".to_string(),
+            author: None,
+            description: Some(description),
+            footer: None,
+            image: None,
+            provider: None,
+            thumbnail: None,
+            timestamp: None,
+            url: None,
+            video: None,
+        }
+    }
+
+    #[test]
+    fn small_batches_are_unchanged() {
+        let body = DiscordWebhookBody {
+            content: "Imported: Fake Series Season 01".to_string(),
+            embeds: vec![embed(10)],
+        };
+
+        let pages = body.clone().paginate();
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].content, body.content);
+        assert_eq!(pages[0].embeds[0].description, body.embeds[0].description);
+    }
+
+    #[test]
+    fn splits_large_batches() {
+        let body = DiscordWebhookBody {
+            content: "Imported: Fake Series Season 01".to_string(),
+            embeds: vec![embed(500)],
+        };
+
+        let pages = body.clone().paginate();
+
+        assert!(pages.len() > 1);
+        for (i, page) in pages.iter().enumerate() {
+            assert_eq!(
+                page.content,
+                format!(
+                    "Imported: Fake Series Season 01 (part {}/{})",
+                    i + 1,
+                    pages.len()
+                )
+            );
+            assert!(page.embeds.len() <= MAX_EMBEDS);
+            assert!(page.embeds.iter().map(embed_length).sum::<usize>() <= MAX_EMBEDS_LENGTH);
+            for embed in &page.embeds {
+                let description = embed.description.as_deref().unwrap();
+                assert!(description.chars().count() <= MAX_DESCRIPTION_LENGTH);
+            }
+        }
+        // every line makes it through, in order
+        let lines = pages
+            .into_iter()
+            .flat_map(|page| page.message_lines())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, body.message_lines());
+    }
+
+    #[test]
+    fn splits_on_embed_count() {
+        let body = DiscordWebhookBody {
+            content: "Imported: 11 Movies".to_string(),
+            embeds: (0..11).map(|_| embed(1)).collect(),
+        };
+
+        let pages = body.paginate();
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].embeds.len(), 10);
+        assert_eq!(pages[1].embeds.len(), 1);
+        assert_eq!(pages[1].content, "Imported: 11 Movies (part 2/2)");
+    }
+
+    fn episode(episode_number: u64, quality: &str, count: u64) -> (u64, u64, String, String, u64) {
+        (
+            1,
+            episode_number,
+            format!("Fake Episode {}", episode_number),
+            quality.to_string(),
+            count,
+        )
+    }
+
+    #[test]
+    fn compact_collapses_runs() {
+        let episodes = vec![
+            episode(1, "WEBDL-1080p", 1),
+            episode(2, "WEBDL-1080p", 1),
+            episode(3, "WEBDL-1080p", 1),
+            episode(4, "HDTV-720p", 1),
+            episode(5, "HDTV-720p", 3),
+            episode(6, "HDTV-720p", 1),
+            episode(8, "HDTV-720p", 1),
+            episode(9, "HDTV-720p", 1),
+        ];
+
+        assert_eq!(
+            episode_lines(episodes.clone(), RenderStyle::Compact, default_episode_line),
+            vec![
+                "01x01–01x03 [WEBDL-1080p]",
+                "01x04 - Fake Episode 4 [HDTV-720p]",
+                "01x05 - Fake Episode 5 [HDTV-720p] (3x)",
+                "01x06 - Fake Episode 6 [HDTV-720p]",
+                "01x08–01x09 [HDTV-720p]",
+            ]
+        );
+        assert_eq!(
+            episode_lines(episodes, RenderStyle::Full, default_episode_line).len(),
+            8
+        );
+    }
+
+    #[test]
+    fn cuts_long_field_values_and_content() {
+        let mut long_embed = embed(1);
+        long_embed.fields = vec![EmbedField {
+            inline: false,
+            name: "Summary".to_string(),
+            value: "x".repeat(2000),
+        }];
+        let body = DiscordWebhookBody {
+            content: "y".repeat(3000),
+            embeds: vec![long_embed],
+        };
+
+        let pages = body.clone().paginate();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].content.chars().count(), MAX_CONTENT_LENGTH);
+        assert!(pages[0].content.ends_with('…'));
+        assert_eq!(
+            pages[0].embeds[0].fields[0].value.chars().count(),
+            MAX_FIELD_VALUE_LENGTH
+        );
+
+        // the part marker is kept when the content is cut short
+        let body = DiscordWebhookBody {
+            embeds: (0..11).map(|_| embed(1)).collect(),
+            ..body
+        };
+        let pages = body.paginate();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].content.chars().count(), MAX_CONTENT_LENGTH);
+        assert!(pages[1].content.ends_with("… (part 2/2)"));
+    }
+
+    #[test]
+    fn long_descriptions_leave_room_for_fields() {
+        let mut long_embed = embed(200);
+        long_embed.fields = (0..4)
+            .map(|i| EmbedField {
+                inline: false,
+                name: format!("Field {}", i),
+                value: "x".repeat(MAX_FIELD_VALUE_LENGTH),
+            })
+            .collect();
+        long_embed.footer = Some(EmbedFooter {
+            icon_url: None,
+            proxy_icon_url: None,
+            text: "z".repeat(500),
+        });
+        long_embed.author = Some(EmbedAuthor {
+            icon_url: None,
+            name: "Fake Author".to_string(),
+            proxy_icon_url: None,
+            url: None,
+        });
+        let description = long_embed.description.clone().unwrap();
+        let body = DiscordWebhookBody {
+            content: "Imported: Fake Series Season 01".to_string(),
+            embeds: vec![long_embed],
+        };
+
+        let pages = body.paginate();
+        assert!(pages.len() > 1);
+        for page in &pages {
+            let length = page.embeds.iter().map(embed_length).sum::<usize>();
+            assert!(length <= MAX_EMBEDS_LENGTH, "{}", length);
+        }
+        // nothing is lost, the fields and footer end up with the last chunk
+        let descriptions = pages
+            .iter()
+            .flat_map(|page| &page.embeds)
+            .filter_map(|embed| embed.description.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(descriptions.join("\n"), description);
+        let last = pages.last().unwrap().embeds.last().unwrap();
+        assert_eq!(last.fields.len(), 4);
+        assert!(last.footer.is_some());
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::discord::DiscordWebhookBody;
+use crate::destination::PushOptions;
+
+// https://gotify.net/api-docs#/message/createMessage
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GotifyMessage {
+    pub title: String,
+    pub message: String,
+    pub priority: u8,
+    pub extras: Value,
+}
+
+impl GotifyMessage {
+    pub fn new(discord_body: &DiscordWebhookBody, event_type: &str, options: &PushOptions) -> Self {
+        // gotify priorities go from 0 to 10, clients only make noise from 4 up
+        let default_priority = match event_type {
+            "Upgrade" | "Rename" => 2,
+            _ => 5,
+        };
+
+        GotifyMessage {
+            title: discord_body.content.clone(),
+            message: discord_body.message_lines().join("\n"),
+            priority: options
+                .priorities
+                .get(event_type)
+                .copied()
+                .unwrap_or(default_priority),
+            extras: json!({
+                "client::display": { "contentType": "text/markdown" }
+            }),
+        }
+    }
+}
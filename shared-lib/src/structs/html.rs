@@ -0,0 +1,30 @@
+// helpers for destinations that take HTML formatted messages
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// escape a line of a Discord description, turning its **bold** markers into <b> tags
+pub(crate) fn to_html(line: &str) -> String {
+    escape_html(line)
+        .split("**")
+        .enumerate()
+        .map(|(i, part)| match i {
+            0 => part.to_string(),
+            _ if i % 2 == 1 => format!("<b>{}", part),
+            _ => format!("</b>{}", part),
+        })
+        .collect::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_bold() {
+        assert_eq!(to_html("**Album** & more"), "<b>Album</b> &amp; more");
+    }
+}
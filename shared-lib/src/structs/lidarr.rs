@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LidarrArtist {
+    pub disambiguation: Option<String>,
+    pub id: Option<u64>,
+    #[serde(rename = "mbId")]
+    pub mb_id: Option<String>,
+    pub name: String,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LidarrAlbum {
+    pub disambiguation: Option<String>,
+    pub id: Option<u64>,
+    #[serde(rename = "mbId")]
+    pub mb_id: Option<String>,
+    #[serde(rename = "releaseDate")]
+    pub release_date: Option<String>,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LidarrTrack {
+    pub id: Option<u64>,
+    pub quality: Option<String>,
+    #[serde(rename = "qualityVersion")]
+    pub quality_version: Option<u64>,
+    #[serde(rename = "releaseGroup")]
+    pub release_group: Option<String>,
+    pub title: String,
+    #[serde(rename = "trackNumber")]
+    pub track_number: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LidarrTrackFile {
+    #[serde(rename = "dateAdded")]
+    pub date_added: Option<String>,
+    pub id: Option<u64>,
+    pub path: Option<String>,
+    pub quality: Option<String>,
+    #[serde(rename = "qualityVersion")]
+    pub quality_version: Option<u64>,
+    #[serde(rename = "releaseGroup")]
+    pub release_group: Option<String>,
+    #[serde(rename = "sceneName")]
+    pub scene_name: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LidarrRelease {
+    pub indexer: Option<String>,
+    pub quality: Option<String>,
+    #[serde(rename = "qualityVersion")]
+    pub quality_version: Option<u64>,
+    #[serde(rename = "releaseGroup")]
+    pub release_group: Option<String>,
+    #[serde(rename = "releaseTitle")]
+    pub release_title: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Clone, Copy, PartialOrd, Ord)]
+pub enum LidarrEventType {
+    Test,
+    Grab,
+    Download,
+    Upgrade,
+    Rename,
+    Retag,
+    ArtistAdd,
+    ArtistDelete,
+    AlbumDelete,
+    Health,
+    ApplicationUpdate,
+    HealthRestored,
+    ManualInteractionRequired,
+    DownloadFailure,
+    ImportFailure,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LidarrRequestBody {
+    // Download events carry a single album, Grab events carry a list of albums
+    pub album: Option<LidarrAlbum>,
+    pub albums: Option<Vec<LidarrAlbum>>,
+    #[serde(rename = "applicationUrl")]
+    pub application_url: Option<String>,
+    pub artist: LidarrArtist,
+    #[serde(rename = "downloadClient")]
+    pub download_client: Option<String>,
+    #[serde(rename = "downloadId")]
+    pub download_id: Option<String>,
+    #[serde(rename = "eventType")]
+    pub event_type: Option<LidarrEventType>,
+    #[serde(rename = "instanceName")]
+    pub instance_name: Option<String>,
+    #[serde(rename = "isUpgrade")]
+    pub is_upgrade: Option<bool>,
+    pub release: Option<LidarrRelease>,
+    pub tracks: Option<Vec<LidarrTrack>>,
+    #[serde(rename = "trackFiles")]
+    pub track_files: Option<Vec<LidarrTrackFile>>,
+}
+
+impl LidarrRequestBody {
+    // the album this event is about, whichever of the two album fields the event type uses
+    pub fn first_album(&self) -> Option<&LidarrAlbum> {
+        self.album
+            .as_ref()
+            .or_else(|| self.albums.as_ref().and_then(|albums| albums.first()))
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct LidarrGroupKey(pub u64, pub u64, pub LidarrEventType);
+
+impl PartialOrd for LidarrGroupKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LidarrGroupKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.0, self.1, &self.2).cmp(&(other.0, other.1, &other.2))
+    }
+}
+
+impl From<&LidarrRequestBody> for LidarrGroupKey {
+    fn from(lidarr_event: &LidarrRequestBody) -> Self {
+        let event_type = match lidarr_event.event_type {
+            Some(LidarrEventType::Download) => {
+                if lidarr_event.is_upgrade.unwrap_or(false) {
+                    LidarrEventType::Upgrade
+                } else {
+                    LidarrEventType::Download
+                }
+            }
+            Some(event_type) => event_type,
+            _ => LidarrEventType::Test,
+        };
+        let artist_id = lidarr_event.artist.id.unwrap_or(0);
+        let album_id = lidarr_event
+            .first_album()
+            .and_then(|album| album.id)
+            .unwrap_or(0);
+        LidarrGroupKey(artist_id, album_id, event_type)
+    }
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    discord::DiscordWebhookBody,
+    html::{escape_html, to_html},
+};
+
+// https://spec.matrix.org/latest/client-server-api/#mroommessage
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatrixMessage {
+    pub msgtype: String,
+    // plain text for clients that don't render HTML
+    pub body: String,
+    pub format: String,
+    pub formatted_body: String,
+}
+
+impl From<&DiscordWebhookBody> for MatrixMessage {
+    fn from(discord_body: &DiscordWebhookBody) -> Self {
+        let mut body = vec![discord_body.content.clone()];
+        let mut formatted_body = vec![format!(
+            "<p><b>{}</b></p>",
+            escape_html(&discord_body.content)
+        )];
+
+        for embed in &discord_body.embeds {
+            let lines = embed
+                .description
+                .as_deref()
+                .unwrap_or_default()
+                .lines()
+                .collect::<Vec<_>>();
+
+            body.push(String::new());
+            if let Some(title) = &embed.title {
+                body.push(title.clone());
+            }
+            body.extend(lines.iter().map(|line| line.replace("**", "")));
+
+            let title = match (&embed.title, embed.color) {
+                (Some(title), Some(color)) => format!(
+                    "<b><font color=\"#{:06X}\">{}</font></b><br>",
+                    color,
+                    escape_html(title)
+                ),
+                (Some(title), None) => format!("<b>{}</b><br>", escape_html(title)),
+                (None, _) => String::new(),
+            };
+            formatted_body.push(format!(
+                "<p>{}{}</p>",
+                title,
+                lines
+                    .iter()
+                    .map(|line| to_html(line))
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            ));
+        }
+
+        MatrixMessage {
+            // bots are expected to send notices so other bots don't respond to them
+            msgtype: "m.notice".to_string(),
+            body: body.join("\n"),
+            format: "org.matrix.custom.html".to_string(),
+            formatted_body: formatted_body.join(""),
+        }
+    }
+}
@@ -1,2 +1,12 @@
+pub mod batch;
+pub mod dead_letter;
 pub mod discord;
+pub mod gotify;
+mod html;
+pub mod lidarr;
+pub mod matrix;
+pub mod ntfy;
+pub mod radarr;
+pub mod slack;
 pub mod sonarr;
+pub mod telegram;
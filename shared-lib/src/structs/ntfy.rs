@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use super::discord::DiscordWebhookBody;
+use crate::destination::PushOptions;
+
+// https://docs.ntfy.sh/publish/#publish-as-json
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NtfyMessage {
+    pub topic: String,
+    pub title: String,
+    pub message: String,
+    pub priority: u8,
+    pub tags: Vec<String>,
+    pub markdown: bool,
+}
+
+impl NtfyMessage {
+    pub fn new(
+        topic: &str,
+        discord_body: &DiscordWebhookBody,
+        event_type: &str,
+        options: &PushOptions,
+    ) -> Self {
+        // ntfy priorities go from 1 (min) to 5 (max) with 3 as the default
+        let default_priority = match event_type {
+            "Upgrade" | "Rename" => 2,
+            _ => 3,
+        };
+        let default_tags = match event_type {
+            "Grab" => vec!["arrow_down"],
+            "Download" => vec!["white_check_mark"],
+            "Upgrade" => vec!["arrow_up"],
+            "Rename" => vec!["pencil2"],
+            _ => vec![],
+        };
+
+        NtfyMessage {
+            topic: topic.to_string(),
+            title: discord_body.content.clone(),
+            message: discord_body.message_lines().join("\n"),
+            priority: options
+                .priorities
+                .get(event_type)
+                .copied()
+                .unwrap_or(default_priority),
+            tags: options
+                .tags
+                .get(event_type)
+                .cloned()
+                .unwrap_or_else(|| default_tags.into_iter().map(str::to_string).collect()),
+            markdown: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discord_body() -> DiscordWebhookBody {
+        DiscordWebhookBody {
+            content: "Upgraded: Fake Series Season 02".to_string(),
+            embeds: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn default_priorities() {
+        let options = PushOptions::default();
+
+        let upgrade = NtfyMessage::new("topic", &discord_body(), "Upgrade", &options);
+        let grab = NtfyMessage::new("topic", &discord_body(), "Grab", &options);
+
+        assert_eq!(upgrade.title, "Upgraded: Fake Series Season 02");
+        assert_eq!(upgrade.priority, 2);
+        assert_eq!(upgrade.tags, vec!["arrow_up".to_string()]);
+        assert_eq!(grab.priority, 3);
+    }
+
+    #[test]
+    fn configured_priorities() {
+        let mut options = PushOptions::default();
+        options.priorities.insert("Grab".to_string(), 5);
+        options
+            .tags
+            .insert("Grab".to_string(), vec!["tv".to_string()]);
+
+        let grab = NtfyMessage::new("topic", &discord_body(), "Grab", &options);
+
+        assert_eq!(grab.priority, 5);
+        assert_eq!(grab.tags, vec!["tv".to_string()]);
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{cmp::Ordering, collections::HashMap};
+
+use super::sonarr::SonarrCustomFormatInfo;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RadarrMovieFile {
+    #[serde(rename = "dateAdded")]
+    pub date_added: Option<String>,
+    pub id: Option<u64>,
+    #[serde(rename = "mediaInfo")]
+    pub media_info: Option<HashMap<String, Value>>,
+    pub path: Option<String>,
+    pub quality: String,
+    #[serde(rename = "qualityVersion")]
+    pub quality_version: Option<u64>,
+    #[serde(rename = "relativePath")]
+    pub relative_path: Option<String>,
+    #[serde(rename = "releaseGroup")]
+    pub release_group: Option<String>,
+    #[serde(rename = "sceneName")]
+    pub scene_name: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RadarrMovie {
+    #[serde(rename = "folderPath")]
+    pub folder_path: Option<String>,
+    pub id: Option<u64>,
+    #[serde(rename = "imdbId")]
+    pub imdb_id: Option<String>,
+    pub overview: Option<String>,
+    #[serde(rename = "releaseDate")]
+    pub release_date: Option<String>,
+    pub title: String,
+    #[serde(rename = "tmdbId")]
+    pub tmdb_id: Option<u64>,
+    pub year: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RadarrRelease {
+    #[serde(rename = "customFormatScore")]
+    pub custom_format_score: Option<i64>,
+    #[serde(rename = "customFormats")]
+    pub custom_formats: Option<Vec<String>>,
+    pub indexer: Option<String>,
+    pub quality: Option<String>,
+    #[serde(rename = "qualityVersion")]
+    pub quality_version: Option<u64>,
+    #[serde(rename = "releaseGroup")]
+    pub release_group: Option<String>,
+    #[serde(rename = "releaseTitle")]
+    pub release_title: Option<String>,
+    pub size: Option<u64>,
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Clone, Copy, PartialOrd, Ord)]
+pub enum RadarrEventType {
+    Test,
+    Grab,
+    Download,
+    Upgrade,
+    Rename,
+    MovieAdded,
+    MovieDelete,
+    MovieFileDelete,
+    Health,
+    ApplicationUpdate,
+    HealthRestored,
+    ManualInteractionRequired,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RadarrRequestBody {
+    #[serde(rename = "applicationUrl")]
+    pub application_url: Option<String>,
+    #[serde(rename = "customFormatInfo")]
+    pub custom_format_info: Option<SonarrCustomFormatInfo>,
+    #[serde(rename = "downloadClient")]
+    pub download_client: Option<String>,
+    #[serde(rename = "downloadId")]
+    pub download_id: Option<String>,
+    #[serde(rename = "eventType")]
+    pub event_type: Option<RadarrEventType>,
+    #[serde(rename = "instanceName")]
+    pub instance_name: Option<String>,
+    pub movie: RadarrMovie,
+    #[serde(rename = "movieFile")]
+    pub movie_file: Option<RadarrMovieFile>,
+    pub release: Option<RadarrRelease>,
+    #[serde(rename = "isUpgrade")]
+    pub is_upgrade: Option<bool>,
+}
+
+// movies don't have a natural season-like grouping in the webhook payload
+// so everything of the same event type within the buffer window goes together
+#[derive(Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct RadarrGroupKey(pub RadarrEventType);
+
+impl PartialOrd for RadarrGroupKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RadarrGroupKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl From<&RadarrRequestBody> for RadarrGroupKey {
+    fn from(radarr_event: &RadarrRequestBody) -> Self {
+        let event_type = match radarr_event.event_type {
+            Some(RadarrEventType::Download) => {
+                if radarr_event.is_upgrade.unwrap_or(false) {
+                    RadarrEventType::Upgrade
+                } else {
+                    RadarrEventType::Download
+                }
+            }
+            Some(event_type) => event_type,
+            _ => RadarrEventType::Test,
+        };
+        RadarrGroupKey(event_type)
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::discord::DiscordWebhookBody;
+
+// Slack rejects messages with more than 50 blocks and section text longer than 3000 characters
+const MAX_SECTIONS: usize = 48;
+const MAX_SECTION_LENGTH: usize = 3000;
+const MAX_HEADER_LENGTH: usize = 150;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlackAttachment {
+    pub color: String,
+    pub blocks: Vec<Value>,
+}
+
+// https://api.slack.com/messaging/webhooks
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlackWebhookBody {
+    // plain text fallback used for notifications
+    pub text: String,
+    pub blocks: Vec<Value>,
+    pub attachments: Vec<SlackAttachment>,
+}
+
+// Slack's mrkdwn uses single asterisks for bold where Discord uses double
+fn to_mrkdwn(text: &str) -> String {
+    text.replace("**", "*")
+}
+
+fn section(text: String) -> Value {
+    json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": text }
+    })
+}
+
+impl From<&DiscordWebhookBody> for SlackWebhookBody {
+    fn from(discord_body: &DiscordWebhookBody) -> Self {
+        let header = discord_body
+            .content
+            .chars()
+            .take(MAX_HEADER_LENGTH)
+            .collect::<String>();
+
+        let attachments = discord_body
+            .embeds
+            .iter()
+            .map(|embed| {
+                let lines = embed
+                    .description
+                    .as_deref()
+                    .unwrap_or_default()
+                    .lines()
+                    .map(to_mrkdwn)
+                    .collect::<Vec<_>>();
+
+                let mut blocks = Vec::new();
+                if let Some(title) = &embed.title {
+                    blocks.push(section(format!("*{}*", title)));
+                }
+
+                if lines.len() <= MAX_SECTIONS {
+                    // one section per episode when it fits
+                    blocks.extend(lines.into_iter().map(section));
+                } else {
+                    // otherwise pack as many lines as fit into each section
+                    let mut chunk = String::new();
+                    for line in lines {
+                        if !chunk.is_empty() && chunk.len() + line.len() + 1 > MAX_SECTION_LENGTH {
+                            blocks.push(section(std::mem::take(&mut chunk)));
+                        }
+                        if !chunk.is_empty() {
+                            chunk.push('\n');
+                        }
+                        chunk.push_str(&line);
+                    }
+                    if !chunk.is_empty() {
+                        blocks.push(section(chunk));
+                    }
+                }
+
+                SlackAttachment {
+                    color: format!("#{:06X}", embed.color.unwrap_or(0xFFFFFF)),
+                    blocks,
+                }
+            })
+            .collect();
+
+        SlackWebhookBody {
+            text: discord_body.content.clone(),
+            blocks: vec![json!({
+                "type": "header",
+                "text": { "type": "plain_text", "text": header }
+            })],
+            attachments,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use twilight_model::channel::message::Embed;
+
+    use super::*;
+
+    fn discord_body(lines: usize) -> DiscordWebhookBody {
+        let description = (1..=lines)
+            .map(|i| format!("01x{:02} - Fake Episode {} [Fake Quality]", i, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        DiscordWebhookBody {
+            content: "Imported: Fake Series Season 01".to_string(),
+            embeds: vec![Embed {
+                title: Some("Fake Series".to_string()),
+                color: Some(0x29A44C),
+                fields: Vec::new(),
+                kind: "rich".to_string(),
+                author: None,
+                description: Some(description),
+                footer: None,
+                image: None,
+                provider: None,
+                thumbnail: None,
+                timestamp: None,
+                url: None,
+                video: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn section_per_episode() {
+        let slack_body = SlackWebhookBody::from(&discord_body(3));
+
+        assert_eq!(slack_body.text, "Imported: Fake Series Season 01");
+        assert_eq!(slack_body.attachments.len(), 1);
+        assert_eq!(slack_body.attachments[0].color, "#29A44C");
+        // title section plus one per episode
+        assert_eq!(slack_body.attachments[0].blocks.len(), 4);
+    }
+
+    #[test]
+    fn packs_large_batches() {
+        let slack_body = SlackWebhookBody::from(&discord_body(200));
+
+        assert!(slack_body.attachments[0].blocks.len() <= MAX_SECTIONS);
+    }
+}
@@ -71,8 +71,36 @@ pub struct SonarrRelease {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SonarrImage {
+    #[serde(rename = "coverType")]
+    pub cover_type: Option<String>,
+    pub url: Option<String>,
+    #[serde(rename = "remoteUrl")]
+    pub remote_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SonarrRenamedEpisodeFile {
+    #[serde(rename = "previousRelativePath")]
+    pub previous_relative_path: Option<String>,
+    #[serde(rename = "relativePath")]
+    pub relative_path: Option<String>,
+    pub quality: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SonarrDownloadStatusMessage {
+    pub title: Option<String>,
+    #[serde(default)]
+    pub messages: Vec<String>,
+}
+
+// health and update events aren't about a series, so they get an empty one
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct SonarrSeries {
     pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<SonarrImage>>,
     #[serde(rename = "imdbId")]
     pub imdb_id: Option<String>,
     pub path: Option<String>,
@@ -114,17 +142,101 @@ pub struct SonarrRequestBody {
     pub download_client: Option<String>,
     #[serde(rename = "downloadId")]
     pub download_id: Option<String>,
+    // missing from renames, series and health events
+    #[serde(default)]
     pub episodes: Vec<SonarrEpisode>,
     #[serde(rename = "eventType")]
     pub event_type: Option<SonarrEventType>,
     #[serde(rename = "instanceName")]
     pub instance_name: Option<String>,
     pub release: Option<SonarrRelease>,
+    #[serde(default)]
     pub series: SonarrSeries,
     #[serde(rename = "episodeFile")]
     pub episode_file: Option<SonarrEpisodeFile>,
     #[serde(rename = "isUpgrade")]
     pub is_upgrade: Option<bool>,
+    // Rename
+    #[serde(rename = "renamedEpisodeFiles")]
+    pub renamed_episode_files: Option<Vec<SonarrRenamedEpisodeFile>>,
+    // EpisodeFileDelete
+    #[serde(rename = "deleteReason")]
+    pub delete_reason: Option<String>,
+    // SeriesDelete
+    #[serde(rename = "deletedFiles")]
+    pub deleted_files: Option<bool>,
+    // Health, HealthRestored and ApplicationUpdate
+    pub level: Option<String>,
+    pub message: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    #[serde(rename = "wikiUrl")]
+    pub wiki_url: Option<String>,
+    #[serde(rename = "previousVersion")]
+    pub previous_version: Option<String>,
+    #[serde(rename = "newVersion")]
+    pub new_version: Option<String>,
+    // ManualInteractionRequired
+    #[serde(rename = "downloadStatus")]
+    pub download_status: Option<String>,
+    #[serde(rename = "downloadStatusMessages")]
+    pub download_status_messages: Option<Vec<SonarrDownloadStatusMessage>>,
+}
+
+impl SonarrSeries {
+    // publicly reachable poster image, the local url needs Sonarr's API key
+    pub fn poster_url(&self) -> Option<&str> {
+        self.images
+            .iter()
+            .flatten()
+            .find(|image| image.cover_type.as_deref() == Some("poster"))
+            .and_then(|image| image.remote_url.as_deref())
+    }
+}
+
+impl SonarrRequestBody {
+    // link to the series in Sonarr, or on TVDB or IMDb when Sonarr's URL isn't set
+    pub fn series_url(&self) -> Option<String> {
+        let series = &self.series;
+        if let (Some(application_url), Some(title_slug)) =
+            (&self.application_url, &series.title_slug)
+        {
+            if !application_url.is_empty() {
+                return Some(format!(
+                    "{}/series/{}",
+                    application_url.trim_end_matches('/'),
+                    title_slug
+                ));
+            }
+        }
+        if let Some(tvdb_id) = series.tvdb_id.filter(|id| *id > 0) {
+            return Some(format!(
+                "https://www.thetvdb.com/?tab=series&id={}",
+                tvdb_id
+            ));
+        }
+        series
+            .imdb_id
+            .as_ref()
+            .filter(|id| !id.is_empty())
+            .map(|imdb_id| format!("https://www.imdb.com/title/{}/", imdb_id))
+    }
+
+    // size of the imported file, or of the release when it was only grabbed
+    pub fn size(&self) -> Option<u64> {
+        self.episode_file
+            .as_ref()
+            .and_then(|episode_file| episode_file.size)
+            .or_else(|| self.release.as_ref()?.size)
+    }
+
+    pub fn release_group(&self) -> Option<&str> {
+        self.episode_file
+            .as_ref()
+            .and_then(|episode_file| episode_file.release_group.as_deref())
+            .or_else(|| self.release.as_ref()?.release_group.as_deref())
+            .filter(|release_group| !release_group.is_empty())
+    }
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, Serialize, Deserialize, Clone, Copy)]
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    discord::DiscordWebhookBody,
+    html::{escape_html, to_html},
+};
+
+// Telegram rejects sendMessage calls with more than 4096 characters of text
+const MAX_MESSAGE_LENGTH: usize = 4096;
+
+// https://core.telegram.org/bots/api#sendmessage
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TelegramMessage {
+    pub chat_id: String,
+    pub text: String,
+    pub parse_mode: String,
+    pub disable_web_page_preview: bool,
+}
+
+impl TelegramMessage {
+    // render a webhook body into as many messages as it takes to stay under Telegram's length limit
+    // lines are never split across messages
+    pub fn from_discord(chat_id: &str, discord_body: &DiscordWebhookBody) -> Vec<TelegramMessage> {
+        let mut lines = vec![format!("<b>{}</b>", escape_html(&discord_body.content))];
+        for embed in &discord_body.embeds {
+            lines.push(String::new());
+            if let Some(title) = &embed.title {
+                lines.push(format!("<b>{}</b>", escape_html(title)));
+            }
+            lines.extend(
+                embed
+                    .description
+                    .as_deref()
+                    .unwrap_or_default()
+                    .lines()
+                    .map(to_html),
+            );
+        }
+
+        let mut texts: Vec<String> = Vec::new();
+        let mut text = String::new();
+        let mut text_length = 0;
+        for line in lines {
+            let line_length = line.chars().count();
+            if text_length > 0 && text_length + line_length + 1 > MAX_MESSAGE_LENGTH {
+                texts.push(std::mem::take(&mut text));
+                text_length = 0;
+            }
+            if text_length > 0 {
+                text.push('\n');
+                text_length += 1;
+            }
+            text.push_str(&line);
+            text_length += line_length;
+        }
+        if !text.is_empty() {
+            texts.push(text);
+        }
+
+        texts
+            .into_iter()
+            .map(|text| TelegramMessage {
+                chat_id: chat_id.to_string(),
+                text,
+                parse_mode: "HTML".to_string(),
+                disable_web_page_preview: true,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use twilight_model::channel::message::Embed;
+
+    use super::*;
+
+    fn discord_body(lines: usize) -> DiscordWebhookBody {
+        let description = (1..=lines)
+            .map(|i| format!("01x{:02} - Fake <Episode> {} [Fake Quality]", i, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        DiscordWebhookBody {
+            content: "Imported: Fake Series Season 01".to_string(),
+            embeds: vec![Embed {
+                title: Some("Fake Series".to_string()),
+                color: Some(0x29A44C),
+                fields: Vec::new(),
+                kind: "rich".to_string(),
+                author: None,
+                description: Some(description),
+                footer: None,
+                image: None,
+                provider: None,
+                thumbnail: None,
+                timestamp: None,
+                url: None,
+                video: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn escapes_html() {
+        let messages = TelegramMessage::from_discord("-100", &discord_body(1));
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].text,
+            "<b>Imported: Fake Series Season 01</b>\n\n<b>Fake Series</b>\n01x01 - Fake &lt;Episode&gt; 1 [Fake Quality]"
+        );
+    }
+
+    #[test]
+    fn splits_long_batches() {
+        let messages = TelegramMessage::from_discord("-100", &discord_body(500));
+
+        assert!(messages.len() > 1);
+        assert!(messages
+            .iter()
+            .all(|message| message.text.chars().count() <= MAX_MESSAGE_LENGTH));
+    }
+}
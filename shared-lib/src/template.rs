@@ -0,0 +1,137 @@
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// user supplied minijinja templates for the parts of a rendered message
+// anything left unset, or that fails to render, falls back to the built in format
+// ex: content = "Imported: {{ series.title }} Season {{ season }}"
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Templates {
+    // the message text above the embed
+    pub content: Option<String>,
+    // the embed title
+    pub title: Option<String>,
+    // one line of the embed description, rendered once per episode
+    pub line: Option<String>,
+    // the embed footer, naming the instance and download client by default
+    pub footer: Option<String>,
+    // the embed color, ex: "#29A44C" or "{% if count > 5 %}#FF0000{% else %}{{ default }}{% endif %}"
+    pub color: Option<String>,
+}
+
+impl Templates {
+    // check that every template compiles, returns one message per broken template
+    pub fn validate(&self) -> Vec<String> {
+        let env = environment();
+        self.named()
+            .filter_map(|(name, template)| {
+                env.template_from_str(template)
+                    .err()
+                    .map(|e| format!("{} template is invalid: {}", name, e))
+            })
+            .collect()
+    }
+
+    fn named(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        [
+            ("content", &self.content),
+            ("title", &self.title),
+            ("line", &self.line),
+            ("footer", &self.footer),
+            ("color", &self.color),
+        ]
+        .into_iter()
+        .filter_map(|(name, template)| template.as_ref().map(|template| (name, template)))
+    }
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    // messages aren't html, don't escape anything
+    env.set_auto_escape_callback(|_| minijinja::AutoEscape::None);
+    env
+}
+
+// render template with context plus the built in value as `default`, falling back to that value
+pub fn render_or(
+    name: &str,
+    template: Option<&str>,
+    mut context: Value,
+    default: String,
+) -> String {
+    let Some(template) = template else {
+        return default;
+    };
+    if let Value::Object(context) = &mut context {
+        context.insert("default".to_string(), Value::String(default.clone()));
+    }
+    render(name, template, context).unwrap_or(default)
+}
+
+// render template with context, logging and returning None if it fails
+fn render(name: &str, template: &str, context: impl Serialize) -> Option<String> {
+    match environment().render_str(template, context) {
+        Ok(rendered) => Some(rendered),
+        Err(e) => {
+            #[cfg(all(feature = "worker", target_arch = "wasm32"))]
+            worker::console_warn!(
+                "Couldn't render {} template, using the default: {}",
+                name,
+                e
+            );
+            #[cfg(feature = "standalone")]
+            tracing::warn!(
+                "Couldn't render {} template, using the default: {}",
+                name,
+                e
+            );
+            None
+        }
+    }
+}
+
+// parse a rendered color template, ex: "#29A44C", "0x29A44C" or "2728012"
+pub fn parse_color(color: &str) -> Option<u32> {
+    let color = color.trim();
+    match color.strip_prefix('#').or_else(|| color.strip_prefix("0x")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => color.parse().ok(),
+    }
+    .filter(|color| *color <= 0xFFFFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn renders_and_validates() {
+        let templates = Templates {
+            content: Some("{{ action }}: {{ series.title }}".to_string()),
+            line: Some("{{ episode.title".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(templates.validate().len(), 1);
+
+        assert_eq!(
+            render(
+                "content",
+                templates.content.as_deref().unwrap(),
+                json!({ "action": "Imported", "series": { "title": "Fake & Series" } })
+            )
+            .as_deref(),
+            Some("Imported: Fake & Series")
+        );
+        assert_eq!(
+            render("line", templates.line.as_deref().unwrap(), json!({})),
+            None
+        );
+
+        assert_eq!(parse_color("#29A44C"), Some(0x29A44C));
+        assert_eq!(parse_color(" 0x0800ff\n"), Some(0x0800FF));
+        assert_eq!(parse_color("green"), None);
+    }
+}
@@ -0,0 +1,556 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use shared_lib::destination::Destination;
+use shared_lib::error::HookbufferError;
+
+use crate::buffer_handler::QueueStatus;
+use crate::dead_letter::DEAD_LETTERS;
+use crate::pause::PAUSES;
+use crate::SharedAppState;
+
+// endpoints for looking at and controlling the pending queues, ex: POST /admin/flush?path=tv
+// they use the top level user and pass, and are turned off when those aren't set
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route("/queues", get(queues))
+        .route("/flush", post(flush))
+        .route("/drop", post(drop_queue))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/dead-letters", get(dead_letters))
+        .route("/dead-letters/retry", post(retry_dead_letter))
+        .route("/dead-letters/delete", post(delete_dead_letter))
+}
+
+#[derive(Deserialize)]
+struct AdminQuery {
+    // inbound path of a queue, ex: "tv" or "api/webhooks/123/abc"
+    path: Option<String>,
+    // destination name, ex: "discord"
+    destination: Option<String>,
+    // dead letter id
+    id: Option<u64>,
+}
+
+pub fn authorize(state: &SharedAppState, headers: &HeaderMap) -> Result<(), HookbufferError> {
+    match state.config.credentials(None) {
+        Some((user, pass)) => shared_lib::auth::check_auth(user, pass, headers),
+        None => Err(HookbufferError::AdminDisabled),
+    }
+}
+
+async fn queues(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    Ok(Json(json!({
+        "queues": all_queues(&state).await,
+        "paused": PAUSES.paused(),
+    })))
+}
+
+// every source's pending queues, sorted by path
+pub async fn all_queues(state: &SharedAppState) -> Vec<QueueStatus> {
+    let mut queues = state.sonarr.queues().await;
+    queues.extend(state.radarr.queues().await);
+    queues.extend(state.lidarr.queues().await);
+    queues.sort_by(|a, b| (&a.path, a.source).cmp(&(&b.path, b.source)));
+    queues
+}
+
+// send a path's queue, or every queue, right away
+async fn flush(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    let path = query.path.as_deref();
+    let (sonarr, radarr, lidarr) = tokio::join!(
+        state.sonarr.flush(path),
+        state.radarr.flush(path),
+        state.lidarr.flush(path)
+    );
+    let flushed = sonarr + radarr + lidarr;
+    if flushed == 0 {
+        if let Some(path) = path {
+            return Err(HookbufferError::NotFound(format!(
+                "Nothing is queued for /{}",
+                path.trim_matches('/')
+            )));
+        }
+    }
+    tracing::info!("[Admin] flushed {} events", flushed);
+
+    Ok(Json(json!({ "flushed": flushed })))
+}
+
+async fn drop_queue(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    let Some(path) = query.path.as_deref() else {
+        return Err(HookbufferError::BadRequest(
+            "Pass the queue to drop as ?path=<path>".to_string(),
+        ));
+    };
+    let dropped = state.sonarr.drop_queue(path).await
+        + state.radarr.drop_queue(path).await
+        + state.lidarr.drop_queue(path).await;
+    if dropped == 0 {
+        return Err(HookbufferError::NotFound(format!(
+            "Nothing is queued for /{}",
+            path.trim_matches('/')
+        )));
+    }
+    tracing::info!("[Admin] dropped {} events queued for /{}", dropped, path);
+
+    Ok(Json(json!({ "dropped": dropped })))
+}
+
+async fn pause(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    let destination = destination(&query)?;
+    if PAUSES.pause(destination) {
+        tracing::info!("[Admin] paused sending to {}", destination);
+    }
+    Ok(Json(json!({ "paused": PAUSES.paused() })))
+}
+
+async fn resume(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    let destination = destination(&query)?;
+    if PAUSES.resume(destination) {
+        tracing::info!("[Admin] resumed sending to {}", destination);
+    }
+    Ok(Json(json!({ "paused": PAUSES.paused() })))
+}
+
+async fn dead_letters(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    Ok(Json(json!({ "dead_letters": DEAD_LETTERS.list() })))
+}
+
+// send a dead letter again right away, it stays a dead letter if that fails too
+async fn retry_dead_letter(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    let id = dead_letter_id(&query)?;
+    // checked up front so the request doesn't hang until the destination is resumed
+    if let Some(letter) = DEAD_LETTERS.get(id) {
+        let destination = letter.webhook.destination.name();
+        if PAUSES.is_paused(destination) {
+            return Err(HookbufferError::BadRequest(format!(
+                "Sending to {} is paused, resume it before retrying",
+                destination
+            )));
+        }
+    }
+    match DEAD_LETTERS.retry(state.journal.as_deref(), id).await {
+        Some(Ok(_)) => Ok(Json(json!({ "delivered": true }))),
+        Some(Err(letter)) => Ok(Json(
+            json!({ "delivered": false, "dead_letter": letter.redacted() }),
+        )),
+        None => Err(unknown_dead_letter(id)),
+    }
+}
+
+async fn delete_dead_letter(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> Result<Json<Value>, HookbufferError> {
+    authorize(&state, &headers)?;
+
+    let id = dead_letter_id(&query)?;
+    if !DEAD_LETTERS.remove(state.journal.as_deref(), id).await {
+        return Err(unknown_dead_letter(id));
+    }
+    tracing::info!("[Admin] deleted dead letter {}", id);
+
+    Ok(Json(json!({ "deleted": id })))
+}
+
+fn dead_letter_id(query: &AdminQuery) -> Result<u64, HookbufferError> {
+    query
+        .id
+        .ok_or_else(|| HookbufferError::BadRequest("Pass the dead letter as ?id=<id>".to_string()))
+}
+
+fn unknown_dead_letter(id: u64) -> HookbufferError {
+    HookbufferError::NotFound(format!("There's no dead letter with id {}", id))
+}
+
+fn destination(query: &AdminQuery) -> Result<&str, HookbufferError> {
+    match query.destination.as_deref() {
+        Some(destination) if Destination::NAMES.contains(&destination) => Ok(destination),
+        Some(destination) => Err(HookbufferError::BadRequest(format!(
+            "Unknown destination {}, must be one of {}",
+            destination,
+            Destination::NAMES.join(", ")
+        ))),
+        None => Err(HookbufferError::BadRequest(
+            "Pass the destination as ?destination=<name>".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::http::{header, HeaderValue, StatusCode};
+    use metrics_exporter_prometheus::PrometheusBuilder;
+    use serial_test::serial;
+    use shared_lib::send::SendError;
+    use shared_lib::structs::discord::{DiscordWebhook, DiscordWebhookBody};
+
+    use super::*;
+    use crate::buffer_handler::tests::target;
+    use crate::buffer_handler::{BufferHandler, Target};
+    use crate::config::Config;
+    use crate::AppState;
+
+    // app state with admin credentials admin:secret, or without any
+    pub(crate) fn state(credentials: bool) -> SharedAppState {
+        let config = if credentials {
+            Config::parse("user = \"admin\"\npass = \"secret\"").unwrap()
+        } else {
+            Config::default()
+        };
+        Arc::new(AppState {
+            config,
+            draining: AtomicBool::new(false),
+            metrics: PrometheusBuilder::new().build_recorder().handle(),
+            journal: None,
+            sonarr: BufferHandler::new(None),
+            radarr: BufferHandler::new(None),
+            lidarr: BufferHandler::new(None),
+        })
+    }
+
+    // the Authorization header for admin:secret
+    pub(crate) fn credentials() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic YWRtaW46c2VjcmV0"),
+        );
+        headers
+    }
+
+    fn query(path: Option<&str>, id: Option<u64>) -> Query<AdminQuery> {
+        Query(AdminQuery {
+            path: path.map(str::to_string),
+            destination: None,
+            id,
+        })
+    }
+
+    // a destination that accepts webhooks sent to /ok and rejects the rest
+    async fn destination() -> String {
+        let app = Router::new()
+            .route(
+                "/api/webhooks/1/ok",
+                post(|| async { StatusCode::NO_CONTENT }),
+            )
+            .route(
+                "/api/webhooks/1/gone",
+                post(|| async { StatusCode::NOT_FOUND }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", address)
+    }
+
+    // queue an episode import for path and wait until it's in the queue
+    pub(crate) async fn queue_event(state: &SharedAppState, path: &str, target: Target) {
+        let shown_path = target.shown_path(path);
+        let event = json!({
+            "eventType": "Download",
+            "series": { "id": 1, "title": "Fake Series" },
+            "episodes": [{
+                "episodeNumber": 1,
+                "seasonNumber": 1,
+                "seriesId": 1,
+                "title": "Fake Episode"
+            }]
+        });
+        state
+            .sonarr
+            .handle(path.to_string(), target, event)
+            .await
+            .unwrap();
+        // the event is added to the queue in the background
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !all_queues(state)
+                .await
+                .iter()
+                .any(|queue| queue.path == shown_path)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn dead_letter(url: String) -> u64 {
+        let webhook = DiscordWebhook::new(
+            url,
+            DiscordWebhookBody {
+                content: "Fake Series".to_string(),
+                embeds: Vec::new(),
+            },
+            Destination::Discord,
+            "batch".to_string(),
+            "Download".to_string(),
+        );
+        let error = SendError {
+            status: Some(StatusCode::INTERNAL_SERVER_ERROR),
+            error: "Destination responded with 500".to_string(),
+            attempts: 3,
+            sent_parts: 0,
+        };
+        DEAD_LETTERS.add(None, None, webhook, &error).await;
+        // ids count up, so it's the last one
+        DEAD_LETTERS.list().last().unwrap().id
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn admin_is_disabled_without_credentials() {
+        let state = state(false);
+        std::env::remove_var("HOOKBUFFER_USER");
+        std::env::remove_var("HOOKBUFFER_PASS");
+
+        let result = queues(State(state.clone()), credentials()).await;
+        assert_eq!(result.unwrap_err(), HookbufferError::AdminDisabled);
+        let result = flush(State(state), credentials(), query(None, None)).await;
+        assert_eq!(result.unwrap_err(), HookbufferError::AdminDisabled);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn flush_sends_a_queue() {
+        let state = state(true);
+        let url = format!("{}/api/webhooks/1/ok", destination().await);
+        queue_event(&state, "api/webhooks/2/flush", target(url)).await;
+
+        let result = flush(State(state.clone()), HeaderMap::new(), query(None, None)).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            HookbufferError::Unauthorized(_)
+        ));
+
+        let Json(flushed) = flush(
+            State(state.clone()),
+            credentials(),
+            query(Some("api/webhooks/2/flush"), None),
+        )
+        .await
+        .unwrap();
+        assert_eq!(flushed, json!({ "flushed": 1 }));
+        assert!(all_queues(&state).await.is_empty());
+
+        let result = flush(
+            State(state),
+            credentials(),
+            query(Some("api/webhooks/2/flush"), None),
+        )
+        .await;
+        assert!(matches!(result.unwrap_err(), HookbufferError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn drop_discards_a_queue() {
+        let state = state(true);
+        let url = format!("{}/api/webhooks/1/ok", destination().await);
+        queue_event(&state, "api/webhooks/2/drop", target(url)).await;
+        // the token is left out when the queues are listed
+        let queues = all_queues(&state).await;
+        assert_eq!(queues[0].path, "api/webhooks/2/[redacted]");
+
+        let result = drop_queue(State(state.clone()), credentials(), query(None, None)).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            HookbufferError::BadRequest(_)
+        ));
+
+        let Json(dropped) = drop_queue(
+            State(state.clone()),
+            credentials(),
+            query(Some("api/webhooks/2/[redacted]"), None),
+        )
+        .await
+        .unwrap();
+        assert_eq!(dropped, json!({ "dropped": 1 }));
+        assert!(all_queues(&state).await.is_empty());
+
+        let result = drop_queue(
+            State(state),
+            credentials(),
+            query(Some("api/webhooks/2/drop"), None),
+        )
+        .await;
+        assert!(matches!(result.unwrap_err(), HookbufferError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn pauses_known_destinations() {
+        let state = state(true);
+        let pause_query = |destination: Option<&str>| {
+            Query(AdminQuery {
+                path: None,
+                destination: destination.map(str::to_string),
+                id: None,
+            })
+        };
+
+        let result = pause(
+            State(state.clone()),
+            credentials(),
+            pause_query(Some("fax")),
+        )
+        .await;
+        assert!(matches!(
+            result.unwrap_err(),
+            HookbufferError::BadRequest(_)
+        ));
+        let result = pause(State(state.clone()), credentials(), pause_query(None)).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            HookbufferError::BadRequest(_)
+        ));
+
+        let Json(paused) = pause(
+            State(state.clone()),
+            credentials(),
+            pause_query(Some("ntfy")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(paused["paused"], json!(["ntfy"]));
+        let Json(paused) = resume(State(state), credentials(), pause_query(Some("ntfy")))
+            .await
+            .unwrap();
+        assert_eq!(paused["paused"], json!([]));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn lists_dead_letters_without_tokens() {
+        let state = state(true);
+        let id = dead_letter("https://discord.com/api/webhooks/1/token".to_string()).await;
+
+        let Json(listed) = dead_letters(State(state), credentials()).await.unwrap();
+        let letter = listed["dead_letters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|letter| letter["id"] == id)
+            .unwrap();
+        assert_eq!(
+            letter["webhook"]["url"],
+            "https://discord.com/api/webhooks/1/[redacted]"
+        );
+        assert_eq!(letter["status"], 500);
+        assert_eq!(letter["attempts"], 3);
+        // the stored dead letter keeps the token so it can still be retried
+        assert_eq!(
+            DEAD_LETTERS.get(id).unwrap().webhook.url,
+            "https://discord.com/api/webhooks/1/token"
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn retries_dead_letters() {
+        let state = state(true);
+        let destination = destination().await;
+
+        let id = dead_letter(format!("{}/api/webhooks/1/gone", destination)).await;
+        let Json(retried) =
+            retry_dead_letter(State(state.clone()), credentials(), query(None, Some(id)))
+                .await
+                .unwrap();
+        assert_eq!(retried["delivered"], false);
+        assert_eq!(retried["dead_letter"]["status"], 404);
+        assert_eq!(retried["dead_letter"]["attempts"], 4);
+        assert_eq!(
+            retried["dead_letter"]["webhook"]["url"],
+            format!("{}/api/webhooks/1/[redacted]", destination)
+        );
+        assert!(DEAD_LETTERS.get(id).is_some());
+
+        let id = dead_letter(format!("{}/api/webhooks/1/ok", destination)).await;
+        let Json(retried) =
+            retry_dead_letter(State(state.clone()), credentials(), query(None, Some(id)))
+                .await
+                .unwrap();
+        assert_eq!(retried, json!({ "delivered": true }));
+        assert!(DEAD_LETTERS.get(id).is_none());
+
+        let result = retry_dead_letter(State(state), credentials(), query(None, Some(id))).await;
+        assert!(matches!(result.unwrap_err(), HookbufferError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn deletes_dead_letters() {
+        let state = state(true);
+        let id = dead_letter("https://discord.com/api/webhooks/1/delete".to_string()).await;
+
+        let result =
+            delete_dead_letter(State(state.clone()), credentials(), query(None, None)).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            HookbufferError::BadRequest(_)
+        ));
+
+        let Json(deleted) =
+            delete_dead_letter(State(state.clone()), credentials(), query(None, Some(id)))
+                .await
+                .unwrap();
+        assert_eq!(deleted, json!({ "deleted": id }));
+        assert!(DEAD_LETTERS.get(id).is_none());
+
+        let result = delete_dead_letter(State(state), credentials(), query(None, Some(id))).await;
+        assert!(matches!(result.unwrap_err(), HookbufferError::NotFound(_)));
+    }
+}
@@ -0,0 +1,781 @@
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared_lib::debounce::flush_delay;
+use shared_lib::destination::Destination;
+use shared_lib::error::HookbufferError;
+use shared_lib::send::SendError;
+use shared_lib::source::{build_webhook, group_events, group_id, stable_hash, Delivery, Source};
+use shared_lib::structs::discord::{DiscordWebhook, RenderStyle};
+use shared_lib::template::Templates;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tokio_util::task::TaskTracker;
+
+use crate::dead_letter::DEAD_LETTERS;
+use crate::history::{DeliveryRecord, HISTORY};
+use crate::journal::{Journal, JournaledEvent};
+use crate::lifecycle::Lifecycle;
+use crate::pause::PAUSES;
+
+pub struct BufferHandler<S: Source> {
+    // this will hold the state for each ongoing timer and queue of requests
+    // the HashMap key will be the URL of the request, and the value will be the state for that URL
+    timers: Arc<Mutex<HashMap<String, TimerState<S>>>>,
+    // when set, queued events and outgoing webhooks are recorded so they survive a restart
+    journal: Option<Arc<Journal>>,
+    // grab messages waiting for their imports, for targets with lifecycle messages on
+    lifecycle: Arc<Lifecycle>,
+    // events on their way into a queue and webhooks being sent, so shutting down can wait for them
+    tasks: TaskTracker,
+    source: PhantomData<fn() -> S>,
+}
+
+impl<S: Source> Default for BufferHandler<S> {
+    fn default() -> Self {
+        BufferHandler::new(None)
+    }
+}
+
+impl<S: Source> Clone for BufferHandler<S> {
+    fn clone(&self) -> Self {
+        BufferHandler {
+            timers: Arc::clone(&self.timers),
+            journal: self.journal.clone(),
+            lifecycle: Arc::clone(&self.lifecycle),
+            tasks: self.tasks.clone(),
+            source: PhantomData,
+        }
+    }
+}
+
+// where a path's groups are sent and how long they wait before sending
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Target {
+    pub destination: Destination,
+    pub url: String,
+    pub debounce: Duration,
+    // longest a queue is held after its first webhook, even if more keep arriving
+    pub max_wait: Duration,
+    // event types to buffer, anything else is dropped. empty buffers everything
+    pub event_types: Vec<String>,
+    // journals written before styles existed won't have this set
+    #[serde(default)]
+    pub style: RenderStyle,
+    // replaces parts of the rendered message, unset uses the built in format
+    #[serde(default)]
+    pub templates: Option<Templates>,
+    // post one message per grab batch and edit it as the imports arrive, Discord only
+    #[serde(default)]
+    pub lifecycle: bool,
+    // name of the configured route, used to label metrics
+    #[serde(default)]
+    pub route: Option<String>,
+}
+
+impl Target {
+    // label for the path's metrics, paths without a configured route are hashed
+    // so the webhook tokens in them don't end up in metrics
+    fn metrics_label(&self, request_path: &str) -> String {
+        match &self.route {
+            Some(route) => route.clone(),
+            // stable across Rust releases so upgrading doesn't start new series
+            None => format!("path-{:016x}", stable_hash(request_path)),
+        }
+    }
+
+    // the path as the admin API shows it, with the webhook token in it redacted
+    // route paths are named in the config and don't hold one
+    pub fn shown_path(&self, request_path: &str) -> String {
+        match &self.route {
+            Some(_) => request_path.to_string(),
+            None => self.destination.redact_path(request_path),
+        }
+    }
+}
+
+// a pending queue as shown by the admin API
+#[derive(Debug, Serialize)]
+pub struct QueueStatus {
+    pub source: &'static str,
+    pub path: String,
+    pub route: Option<String>,
+    pub destination: &'static str,
+    pub queue_length: usize,
+    // ids of the groups the queue will be sent as, see shared_lib::source::group_id
+    pub groups: Vec<String>,
+    // how long until the timer expires, a paused queue waits longer
+    pub flush_in_secs: f64,
+    pub paused: bool,
+}
+
+// whether an admin ?path= names the queue for request_path, either as it was posted to or as it's shown
+// leading and trailing slashes are ignored
+fn names_queue(path: &str, request_path: &str, target: &Target) -> bool {
+    let path = path.trim_matches('/');
+    path == request_path.trim_matches('/')
+        || path == target.shown_path(request_path).trim_matches('/')
+}
+
+struct TimerState<S: Source> {
+    // this will hold the queue of requests for this URL
+    queue: Vec<S::Event>,
+    // journal ids of the events in the queue
+    journal_ids: Vec<u64>,
+    // when each event in the queue arrived
+    queued_at: Vec<Instant>,
+    // where the queue gets sent when the timer expires
+    target: Target,
+    // when the first request in the current queue arrived
+    first_queued: Instant,
+    // this will hold when the timer for this URL is set to expire
+    timer_end: Instant,
+    // this will hold the current timer ID for this URL
+    timer_id: usize,
+}
+
+impl<S: Source> BufferHandler<S> {
+    pub fn new(journal: Option<Arc<Journal>>) -> Self {
+        BufferHandler {
+            timers: Arc::default(),
+            journal,
+            lifecycle: Arc::default(),
+            tasks: TaskTracker::new(),
+            source: PhantomData,
+        }
+    }
+
+    // parse and check the webhook right away so the sender hears about problems,
+    // only adding it to the queue happens in the background
+    // returns a message saying what happened to it
+    pub async fn handle(
+        &self,
+        request_path: String,
+        target: Target,
+        body: Value,
+    ) -> Result<&'static str, HookbufferError> {
+        // parse the request body into the source's event type
+        let event = S::parse(body).map_err(|e| {
+            tracing::warn!("[{}] Rejected webhook: {}", S::NAME, e);
+            HookbufferError::from(e)
+        })?;
+
+        let event_type = S::event_type(&event);
+        metrics::counter!(
+            "hookbuffer_events_received_total",
+            "source" => S::NAME,
+            "event_type" => event_type.clone()
+        )
+        .increment(1);
+
+        // the application checking the connection when the webhook is saved
+        if S::delivery(&event) == Delivery::Test {
+            tracing::info!("[Test] {} connection test received", S::NAME);
+            return Ok("Test received");
+        }
+
+        if !target.event_types.is_empty() && !target.event_types.contains(&event_type) {
+            tracing::info!("[Filtered] {}", S::describe(&event));
+            return Ok("Event type filtered out");
+        }
+
+        tracing::info!("[Recieved] {}", S::describe(&event));
+
+        if S::delivery(&event) == Delivery::Immediate {
+            self.tasks
+                .spawn(send_immediately::<S>(self.journal.clone(), target, event));
+            return Ok("Request forwarded");
+        }
+
+        // journaled before answering so an accepted webhook survives a restart
+        let journal_id = match &self.journal {
+            Some(journal) => Some(
+                journal
+                    .record_event::<S>(&request_path, &target, &event)
+                    .await,
+            ),
+            None => None,
+        };
+        let handler = self.clone();
+        self.tasks.spawn(async move {
+            handler
+                .enqueue(request_path, target, event, journal_id)
+                .await;
+        });
+
+        Ok("Request added to queue")
+    }
+
+    // put an event from the journal back in its queue after a restart
+    pub async fn restore(&self, journaled: JournaledEvent) {
+        match serde_json::from_value::<S::Event>(journaled.event) {
+            Ok(event) => {
+                tracing::info!("[Restored] {}", S::describe(&event));
+                self.enqueue(journaled.path, journaled.target, event, Some(journaled.id))
+                    .await;
+            }
+            Err(e) => tracing::error!(
+                "[Journal] couldn't restore {} event {}: {}",
+                S::NAME,
+                journaled.id,
+                e
+            ),
+        }
+    }
+
+    async fn enqueue(
+        &self,
+        request_path: String,
+        target: Target,
+        event: S::Event,
+        journal_id: Option<u64>,
+    ) {
+        let now = Instant::now();
+        let depth = metrics::gauge!(
+            "hookbuffer_queue_depth",
+            "source" => S::NAME,
+            "route" => target.metrics_label(&request_path)
+        );
+
+        {
+            let mut timers = self.timers.lock().await;
+
+            // check if there is already a TimerState for this URL
+            if let Some(timer_state) = timers.get_mut(&request_path) {
+                // if there is a TimerState, add this request to the queue and update the timer_end Instant
+                if timer_state.queue.is_empty() {
+                    timer_state.first_queued = now;
+                }
+                timer_state.queue.push(event);
+                timer_state.journal_ids.extend(journal_id);
+                timer_state.queued_at.push(now);
+                depth.set(timer_state.queue.len() as f64);
+                // the timer restarts with every request but can't be pushed past the max wait
+                timer_state.timer_end = now
+                    + flush_delay(
+                        target.debounce,
+                        target.max_wait,
+                        now - timer_state.first_queued,
+                    );
+                timer_state.target = target;
+            } else {
+                // if there isn't a TimerState, create one with this request in the queue and a new timer_end Instant
+                tracing::info!("[Timer] new {} timer started for {}", S::NAME, request_path);
+                let timer_state = TimerState {
+                    queue: vec![event],
+                    journal_ids: journal_id.into_iter().collect(),
+                    queued_at: vec![now],
+                    first_queued: now,
+                    timer_end: now + target.debounce.min(target.max_wait),
+                    target,
+                    timer_id: 0,
+                };
+                timers.insert(request_path.clone(), timer_state);
+                depth.set(1.0);
+            }
+        }
+
+        // now that the request has been added to the queue and the timer_end Instant has been updated
+        // we need to start the timer if it's not already running
+        self.start_timer(request_path).await;
+    }
+
+    // send pending queues right away instead of waiting for their timers, even if their destination is paused
+    // flushes the queue for the given path, or every queue when there isn't one
+    // returns how many events were sent
+    pub async fn flush(&self, only_path: Option<&str>) -> usize {
+        let pending = {
+            let mut timers = self.timers.lock().await;
+            timers
+                .iter_mut()
+                .filter(|(request_path, timer_state)| {
+                    !timer_state.queue.is_empty()
+                        && only_path
+                            .is_none_or(|path| names_queue(path, request_path, &timer_state.target))
+                })
+                .map(|(request_path, timer_state)| {
+                    // stops the running timer from picking the queue up again
+                    timer_state.timer_id += 1;
+                    tracing::info!(
+                        "[Timer] flushing {} timer for {} with {} requests in queue",
+                        S::NAME,
+                        request_path,
+                        timer_state.queue.len()
+                    );
+                    timer_state.take(request_path)
+                })
+                .collect::<Vec<_>>()
+        };
+        let flushed = pending.iter().map(|(queue, _, _)| queue.len()).sum();
+
+        futures::future::join_all(pending.into_iter().map(|(queue, journal_ids, target)| {
+            process_timer_queue::<S>(
+                self.journal.clone(),
+                &self.lifecycle,
+                journal_ids,
+                target,
+                queue,
+            )
+        }))
+        .await;
+        flushed
+    }
+
+    // throw away the queue for the given path without sending it, returns how many events were dropped
+    pub async fn drop_queue(&self, path: &str) -> usize {
+        let mut timers = self.timers.lock().await;
+        let mut dropped = 0;
+        for (request_path, timer_state) in timers.iter_mut() {
+            if timer_state.queue.is_empty() || !names_queue(path, request_path, &timer_state.target)
+            {
+                continue;
+            }
+            // stops the running timer from picking the queue up again
+            timer_state.timer_id += 1;
+            let (queue, journal_ids, _) = timer_state.take(request_path);
+            tracing::info!(
+                "[Timer] dropped {} {} requests queued for {}",
+                queue.len(),
+                S::NAME,
+                request_path
+            );
+            if let Some(journal) = &self.journal {
+                journal.record_flush(journal_ids, &[]).await;
+            }
+            dropped += queue.len();
+        }
+        dropped
+    }
+
+    // send everything that's queued and wait for whatever is still being added or sent, for shutting down
+    // new webhooks have to be turned away first or this may never finish
+    pub async fn drain(&self) {
+        self.tasks.close();
+        self.flush(None).await;
+        self.tasks.wait().await;
+        // events accepted right before shutting down may have reached their queue while waiting
+        self.flush(None).await;
+    }
+
+    // every queue that has something waiting in it
+    pub async fn queues(&self) -> Vec<QueueStatus> {
+        let timers = self.timers.lock().await;
+        let now = Instant::now();
+        timers
+            .iter()
+            .filter(|(_, timer_state)| !timer_state.queue.is_empty())
+            .map(|(request_path, timer_state)| {
+                let mut groups = timer_state
+                    .queue
+                    .iter()
+                    .map(|event| group_id::<S>(&S::group_key(event)))
+                    .collect::<Vec<_>>();
+                groups.sort();
+                groups.dedup();
+                let destination = timer_state.target.destination.name();
+                QueueStatus {
+                    source: S::NAME,
+                    path: timer_state.target.shown_path(request_path),
+                    route: timer_state.target.route.clone(),
+                    destination,
+                    queue_length: timer_state.queue.len(),
+                    groups,
+                    flush_in_secs: timer_state
+                        .timer_end
+                        .saturating_duration_since(now)
+                        .as_secs_f64(),
+                    paused: PAUSES.is_paused(destination),
+                }
+            })
+            .collect()
+    }
+
+    async fn start_timer(&self, request_path: String) {
+        // get the needed information first and then release the lock
+        let (timer_id, timer_end) = {
+            let mut timers = self.timers.lock().await;
+
+            if let Some(timer_state) = timers.get_mut(&request_path) {
+                // increment the timer ID
+                timer_state.timer_id += 1;
+                let timer_id = timer_state.timer_id;
+
+                // start a new timer
+                let timer_end = timer_state.timer_end;
+
+                (timer_id, timer_end) // return this information to use later
+            } else {
+                return; // no timer state found for this request_path
+            }
+        };
+
+        // now you're free to start the timer without holding the lock
+        let timers = Arc::clone(&self.timers);
+        let timer = process_timer::<S>(
+            timers,
+            self.journal.clone(),
+            Arc::clone(&self.lifecycle),
+            self.tasks.clone(),
+            request_path,
+            timer_id,
+            timer_end,
+        );
+        // timers that are already due, like the ones started while draining, are waited for when shutting down
+        // the rest are flushed instead, so waiting for them would only hold shutting down up
+        if timer_end <= Instant::now() {
+            self.tasks.spawn(timer);
+        } else {
+            tokio::spawn(timer);
+        }
+    }
+}
+
+impl<S: Source> TimerState<S> {
+    // empty the queue to send it, returning its events, their journal ids and where they go
+    fn take(&mut self, request_path: &str) -> (Vec<S::Event>, Vec<u64>, Target) {
+        for queued_at in self.queued_at.drain(..) {
+            metrics::histogram!("hookbuffer_buffer_seconds", "source" => S::NAME)
+                .record(queued_at.elapsed().as_secs_f64());
+        }
+        metrics::gauge!(
+            "hookbuffer_queue_depth",
+            "source" => S::NAME,
+            "route" => self.target.metrics_label(request_path)
+        )
+        .set(0.0);
+
+        (
+            std::mem::take(&mut self.queue),
+            std::mem::take(&mut self.journal_ids),
+            self.target.clone(),
+        )
+    }
+}
+
+// this function is spawned when a url timer expires and it processes the queue of requests
+async fn process_timer<S: Source>(
+    timers: Arc<Mutex<HashMap<String, TimerState<S>>>>,
+    journal: Option<Arc<Journal>>,
+    lifecycle: Arc<Lifecycle>,
+    tasks: TaskTracker,
+    request_path: String,
+    timer_id: usize,
+    timer_end: Instant,
+) {
+    let duration = timer_end - Instant::now();
+    tokio::time::sleep(duration).await;
+
+    // queues for a paused destination wait for it to be resumed
+    let destination = {
+        let timers = timers.lock().await;
+        timers
+            .get(&request_path)
+            .map(|timer_state| timer_state.target.destination.name())
+    };
+    if let Some(destination) = destination {
+        PAUSES.wait(destination).await;
+    }
+
+    let timer_state_queue = {
+        let mut timers = timers.lock().await;
+        if let Some(timer_state) = timers.get_mut(&request_path) {
+            // only proceed if the timer ID hasn't changed
+            // this is how we know the timer hasn't been reset since this function was spawned
+            if timer_state.timer_id == timer_id {
+                tracing::info!(
+                    "[Timer] {} timer expired for {} with {} requests in queue",
+                    S::NAME,
+                    request_path,
+                    timer_state.queue.len()
+                );
+
+                // take ownership of the queue, leaving an empty one in its place
+                Some(timer_state.take(&request_path))
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+
+    if let Some((queue, journal_ids, target)) = timer_state_queue {
+        // only tracked once it's sending, a timer that's still waiting is flushed instead when shutting down
+        let _sending = tasks.token();
+        process_timer_queue::<S>(journal, &lifecycle, journal_ids, target, queue).await;
+    }
+}
+
+// render a single event and send it without waiting for a timer
+async fn send_immediately<S: Source>(
+    journal: Option<Arc<Journal>>,
+    target: Target,
+    event: S::Event,
+) {
+    let webhook = build_webhook::<S>(
+        target.url.clone(),
+        &target.destination,
+        vec![event],
+        flushed_at(),
+        target.style,
+        target.templates.as_ref(),
+    );
+    let journal_id = match &journal {
+        Some(journal) => journal
+            .record_flush(Vec::new(), std::slice::from_ref(&webhook))
+            .await
+            .pop(),
+        None => None,
+    };
+    deliver(journal.as_deref(), [(journal_id, webhook)]).await;
+}
+
+// milliseconds since the epoch, used to tell apart flushes of the same group
+fn flushed_at() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+async fn process_timer_queue<S: Source>(
+    journal: Option<Arc<Journal>>,
+    lifecycle: &Lifecycle,
+    journal_ids: Vec<u64>,
+    target: Target,
+    queue: Vec<S::Event>,
+) {
+    let mut groups = group_events::<S>(queue).into_iter().collect::<Vec<_>>();
+    groups.sort_by_key(|(group_key, _)| *group_key);
+
+    let flushed_at = flushed_at();
+    let lifecycle_messages = target.lifecycle && target.destination == Destination::Discord;
+
+    let mut webhooks: Vec<DiscordWebhook> = Vec::new();
+    // the downloads of each grab webhook that's posted as a lifecycle message
+    let mut grabs: Vec<Option<Vec<(String, usize)>>> = Vec::new();
+    for (_, mut group) in groups {
+        metrics::counter!("hookbuffer_groups_flushed_total", "source" => S::NAME).increment(1);
+        let mut downloads = None;
+        if lifecycle_messages && S::event_type(&group[0]) == "Grab" {
+            downloads = Some(group.iter().filter_map(S::download).collect());
+        } else if lifecycle_messages {
+            // imports of a tracked grab edit its message instead of getting one of their own
+            let (tracked, untracked): (Vec<_>, Vec<_>) = group.into_iter().partition(|event| {
+                S::download(event)
+                    .is_some_and(|(download_id, _)| lifecycle.is_tracked(&download_id))
+            });
+            for edit in lifecycle.imported(tracked.iter().filter_map(S::download)) {
+                webhooks.push(edit);
+                grabs.push(None);
+            }
+            if untracked.is_empty() {
+                continue;
+            }
+            group = untracked;
+        }
+
+        webhooks.push(build_webhook::<S>(
+            target.url.clone(),
+            &target.destination,
+            group,
+            flushed_at,
+            target.style,
+            target.templates.as_ref(),
+        ));
+        grabs.push(downloads);
+    }
+
+    let webhook_ids = match &journal {
+        Some(journal) => journal
+            .record_flush(journal_ids, &webhooks)
+            .await
+            .into_iter()
+            .map(Some)
+            .collect(),
+        None => vec![None; webhooks.len()],
+    };
+
+    for ((journal_id, webhook), downloads) in webhook_ids.into_iter().zip(webhooks).zip(grabs) {
+        let record = DeliveryRecord::new(&webhook);
+        let result = match downloads {
+            Some(downloads) => lifecycle.post_grab(webhook.clone(), downloads).await,
+            None => shared_lib::send::send_webhook(webhook.clone()).await,
+        };
+        HISTORY.record(record, &result);
+        settle(journal.as_deref(), journal_id, webhook, result).await;
+    }
+}
+
+// send webhooks one at a time, marking each one done in the journal once it's been delivered
+// and keeping the ones that fail as dead letters
+// pacing between sends is left to the rate limiter in shared_lib::send
+pub async fn deliver(
+    journal: Option<&Journal>,
+    webhooks: impl IntoIterator<Item = (Option<u64>, DiscordWebhook)>,
+) {
+    for (journal_id, webhook) in webhooks {
+        PAUSES.wait(webhook.destination.name()).await;
+        let record = DeliveryRecord::new(&webhook);
+        let result = shared_lib::send::send_webhook(webhook.clone()).await;
+        HISTORY.record(record, &result);
+        settle(journal, journal_id, webhook, result).await;
+    }
+}
+
+async fn settle(
+    journal: Option<&Journal>,
+    journal_id: Option<u64>,
+    webhook: DiscordWebhook,
+    result: Result<StatusCode, SendError>,
+) {
+    match (result, journal, journal_id) {
+        (Ok(_), Some(journal), Some(journal_id)) => journal.record_delivered(journal_id).await,
+        (Ok(_), _, _) => {}
+        (Err(error), journal, journal_id) => {
+            DEAD_LETTERS.add(journal, journal_id, webhook, &error).await
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::json;
+    use shared_lib::source::Sonarr;
+    use shared_lib::structs::discord::DiscordWebhookBody;
+
+    use super::*;
+
+    // a Discord target that holds its queue long enough that only flushing or draining sends it
+    pub(crate) fn target(url: String) -> Target {
+        Target {
+            destination: Destination::Discord,
+            url,
+            debounce: Duration::from_secs(600),
+            max_wait: Duration::from_secs(600),
+            event_types: Vec::new(),
+            style: RenderStyle::Full,
+            templates: None,
+            lifecycle: false,
+            route: None,
+        }
+    }
+
+    #[test]
+    fn metrics_labels_stay_the_same() {
+        let mut target = target("https://discord.com/api/webhooks/1/abc".to_string());
+        assert_eq!(
+            target.metrics_label("api/webhooks/1/abc"),
+            "path-35781fce671978ce"
+        );
+        target.route = Some("tv".to_string());
+        assert_eq!(target.metrics_label("tv"), "tv");
+    }
+
+    #[tokio::test]
+    async fn draining_sends_what_was_just_accepted() {
+        let received = Arc::new(StdMutex::new(Vec::<String>::new()));
+        let app = Router::new()
+            .route(
+                "/api/webhooks/1/abc",
+                post(
+                    |State(received): State<Arc<StdMutex<Vec<String>>>>,
+                     Json(body): Json<DiscordWebhookBody>| async move {
+                        received.lock().unwrap().push(body.content);
+                        StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .with_state(Arc::clone(&received));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let handler = BufferHandler::<Sonarr>::default();
+        let target = target(format!("http://{}/api/webhooks/1/abc", address));
+        let event = json!({
+            "eventType": "Download",
+            "series": { "id": 1, "title": "Fake Series" },
+            "episodes": [{
+                "episodeNumber": 1,
+                "seasonNumber": 1,
+                "seriesId": 1,
+                "title": "Fake Episode"
+            }]
+        });
+        let message = handler
+            .handle("api/webhooks/1/abc".to_string(), target, event)
+            .await
+            .unwrap();
+        assert_eq!(message, "Request added to queue");
+
+        // the event is still on its way into the queue when draining starts
+        tokio::time::timeout(Duration::from_secs(10), handler.drain())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec!["Imported: Fake Series - 01x01 - Fake Episode"]
+        );
+        assert!(handler.queues().await.is_empty());
+    }
+    #[tokio::test]
+    async fn draining_waits_for_timers_that_are_due() {
+        let received = Arc::new(StdMutex::new(Vec::<String>::new()));
+        let app = Router::new()
+            .route(
+                "/api/webhooks/2/abc",
+                post(
+                    |State(received): State<Arc<StdMutex<Vec<String>>>>,
+                     Json(body): Json<DiscordWebhookBody>| async move {
+                        // slow enough that draining would be done without waiting for the timer
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        received.lock().unwrap().push(body.content);
+                        StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .with_state(Arc::clone(&received));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let handler = BufferHandler::<Sonarr>::default();
+        // what main.rs does with webhooks that arrive while draining
+        let mut target = target(format!("http://{}/api/webhooks/2/abc", address));
+        target.debounce = Duration::ZERO;
+        let event = json!({
+            "eventType": "Download",
+            "series": { "id": 1, "title": "Fake Series" },
+            "episodes": [{
+                "episodeNumber": 1,
+                "seasonNumber": 1,
+                "seriesId": 1,
+                "title": "Fake Episode"
+            }]
+        });
+        handler
+            .handle("api/webhooks/2/abc".to_string(), target, event)
+            .await
+            .unwrap();
+        // the timer has already taken the queue by now
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        tokio::time::timeout(Duration::from_secs(10), handler.drain())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec!["Imported: Fake Series - 01x01 - Fake Episode"]
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Deserialize;
+use shared_lib::destination::Destination;
+use shared_lib::retry::RetryPolicy;
+use shared_lib::structs::discord::RenderStyle;
+use shared_lib::template::Templates;
+use tokio::time::Duration;
+
+use crate::buffer_handler::Target;
+use crate::env;
+
+const SOURCES: [&str; 3] = ["sonarr", "radarr", "lidarr"];
+
+// top level settings, every one of these can be overridden by its env var
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub port: Option<u16>,
+    // basic auth required for every path that doesn't set its own
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    // where webhooks for paths without a route are sent, same as HOOKBUFFER_DESTINATION_URL
+    pub destination_url: Option<String>,
+    // seconds to wait for more webhooks before sending, same as HOOKBUFFER_DEBOUNCE_SECS
+    pub debounce: Option<u64>,
+    // most seconds to hold a queue after its first webhook, same as HOOKBUFFER_MAX_WAIT_SECS
+    pub max_wait: Option<u64>,
+    // full or compact, same as HOOKBUFFER_RENDER_STYLE
+    pub style: Option<RenderStyle>,
+    // edit grab messages as their imports arrive, same as HOOKBUFFER_LIFECYCLE_MESSAGES
+    pub lifecycle: Option<bool>,
+    // seconds to keep sending buffered webhooks after a shutdown signal, same as HOOKBUFFER_SHUTDOWN_DEADLINE_SECS
+    pub shutdown_deadline: Option<u64>,
+    // file queued events and outgoing webhooks are recorded in, same as HOOKBUFFER_JOURNAL_PATH
+    pub journal: Option<String>,
+    // times to send a webhook before a network error or 5xx is given up on, same as HOOKBUFFER_RETRY_ATTEMPTS
+    pub retry_attempts: Option<u32>,
+    // most seconds to spend waiting between those retries, same as HOOKBUFFER_RETRY_MAX_SECS
+    pub retry_max_time: Option<u64>,
+    #[serde(default)]
+    pub routes: BTreeMap<String, RouteConfig>,
+}
+
+// a named inbound path with its own destination and settings
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    // inbound path the application posts to, ex: "tv" for http://hookbuffer:8000/tv
+    pub path: String,
+    // when set, requests must include ?token=<token>
+    pub token: Option<String>,
+    // basic auth for this route, replaces the top level user and pass
+    pub user: Option<String>,
+    pub pass: Option<String>,
+    // destination type in the same shape it's queued in, ex: "discord" or { telegram = { chat_id = "-100" } }
+    #[serde(default)]
+    pub destination: Destination,
+    // full URL the rendered webhooks are sent to
+    pub url: String,
+    // seconds to wait for more webhooks before sending, defaults to the top level debounce
+    pub debounce: Option<u64>,
+    // most seconds to hold a queue after its first webhook, defaults to the top level max_wait
+    pub max_wait: Option<u64>,
+    // full or compact, defaults to the top level style
+    pub style: Option<RenderStyle>,
+    // minijinja templates for the content, title, line, footer and color of rendered messages
+    pub templates: Option<Templates>,
+    // edit grab messages as their imports arrive, defaults to the top level lifecycle
+    pub lifecycle: Option<bool>,
+    // only accept these sources, ex: ["sonarr"], empty accepts all of them
+    #[serde(default)]
+    pub sources: Vec<String>,
+    // only buffer these event types, ex: ["Download", "Upgrade"], empty buffers all of them
+    #[serde(default)]
+    pub event_types: Vec<String>,
+}
+
+impl RouteConfig {
+    pub fn accepts_source(&self, source: &str) -> bool {
+        self.sources.is_empty() || self.sources.iter().any(|s| s.eq_ignore_ascii_case(source))
+    }
+}
+
+impl Config {
+    // load the file named by HOOKBUFFER_CONFIG, without one everything comes from env vars
+    // returns every problem found so they can all be fixed at once
+    pub fn load() -> Result<Config, Vec<String>> {
+        match std::env::var("HOOKBUFFER_CONFIG") {
+            Ok(path) => {
+                tracing::info!("Loading config file {}", path);
+                let contents = std::fs::read_to_string(&path)
+                    .map_err(|e| vec![format!("couldn't read {}: {}", path, e)])?;
+                Config::parse(&contents)
+            }
+            Err(_) => Ok(Config::default()),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Config, Vec<String>> {
+        let config: Config = toml::from_str(contents).map_err(|e| vec![e.to_string()])?;
+        let errors = config.validate();
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.user.is_some() != self.pass.is_some() {
+            errors.push("user and pass must be set together".to_string());
+        }
+        if self.debounce == Some(0) {
+            errors.push("debounce must be at least 1".to_string());
+        }
+        if self.max_wait == Some(0) {
+            errors.push("max_wait must be at least 1".to_string());
+        }
+        if self.retry_attempts == Some(0) {
+            errors.push("retry_attempts must be at least 1".to_string());
+        }
+
+        let mut paths = HashSet::new();
+        for (name, route) in &self.routes {
+            let path = route.path.trim_matches('/');
+            if path.is_empty() {
+                errors.push(format!("routes.{}: path can't be empty", name));
+            } else if is_reserved(path) {
+                errors.push(format!("routes.{}: path {} is reserved", name, path));
+            } else if !paths.insert(path) {
+                errors.push(format!(
+                    "routes.{}: path {} is used by another route",
+                    name, path
+                ));
+            }
+            if !route.url.starts_with("http://") && !route.url.starts_with("https://") {
+                errors.push(format!(
+                    "routes.{}: url must start with http:// or https://",
+                    name
+                ));
+            }
+            if route.debounce == Some(0) {
+                errors.push(format!("routes.{}: debounce must be at least 1", name));
+            }
+            if route.max_wait == Some(0) {
+                errors.push(format!("routes.{}: max_wait must be at least 1", name));
+            }
+            if let Some(templates) = &route.templates {
+                errors.extend(
+                    templates
+                        .validate()
+                        .into_iter()
+                        .map(|e| format!("routes.{}: {}", name, e)),
+                );
+            }
+            if route.user.is_some() != route.pass.is_some() {
+                errors.push(format!(
+                    "routes.{}: user and pass must be set together",
+                    name
+                ));
+            }
+            for source in &route.sources {
+                if !SOURCES.contains(&source.to_lowercase().as_str()) {
+                    errors.push(format!(
+                        "routes.{}: unknown source {}, must be one of {}",
+                        name,
+                        source,
+                        SOURCES.join(", ")
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+
+    pub fn route_for_path(&self, path: &str) -> Option<&RouteConfig> {
+        let path = path.trim_matches('/');
+        self.routes
+            .values()
+            .find(|route| route.path.trim_matches('/') == path)
+    }
+
+    pub fn server_port(&self) -> u16 {
+        // an invalid HOOKBUFFER_PORT falls back to the file's port rather than the default
+        env::get_server_port(self.port.unwrap_or(env::DEFAULT_PORT))
+    }
+
+    pub fn debounce(&self) -> Duration {
+        match (std::env::var("HOOKBUFFER_DEBOUNCE_SECS"), self.debounce) {
+            (Err(_), Some(secs)) => Duration::from_secs(secs),
+            _ => env::get_debounce(),
+        }
+    }
+
+    pub fn max_wait(&self) -> Duration {
+        match (std::env::var("HOOKBUFFER_MAX_WAIT_SECS"), self.max_wait) {
+            (Err(_), Some(secs)) => Duration::from_secs(secs),
+            _ => env::get_max_wait(),
+        }
+    }
+
+    pub fn shutdown_deadline(&self) -> Duration {
+        match (
+            std::env::var("HOOKBUFFER_SHUTDOWN_DEADLINE_SECS"),
+            self.shutdown_deadline,
+        ) {
+            (Err(_), Some(secs)) => Duration::from_secs(secs),
+            _ => env::get_shutdown_deadline(),
+        }
+    }
+
+    pub fn style(&self) -> RenderStyle {
+        match (std::env::var("HOOKBUFFER_RENDER_STYLE"), self.style) {
+            (Err(_), Some(style)) => style,
+            _ => env::get_render_style(),
+        }
+    }
+
+    pub fn lifecycle(&self) -> bool {
+        match (
+            std::env::var("HOOKBUFFER_LIFECYCLE_MESSAGES"),
+            self.lifecycle,
+        ) {
+            (Err(_), Some(lifecycle)) => lifecycle,
+            _ => env::get_lifecycle_messages(),
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: match (
+                std::env::var("HOOKBUFFER_RETRY_ATTEMPTS"),
+                self.retry_attempts,
+            ) {
+                (Err(_), Some(attempts)) => attempts,
+                _ => env::get_retry_attempts(),
+            },
+            max_retry_time: match (
+                std::env::var("HOOKBUFFER_RETRY_MAX_SECS"),
+                self.retry_max_time,
+            ) {
+                (Err(_), Some(secs)) => Duration::from_secs(secs),
+                _ => env::get_retry_max_time(),
+            },
+        }
+    }
+
+    pub fn journal_path(&self) -> Option<String> {
+        env::get_journal_path().or_else(|| self.journal.clone())
+    }
+
+    pub fn route_target(&self, route: &RouteConfig) -> Target {
+        Target {
+            destination: route.destination.clone(),
+            url: route.url.clone(),
+            debounce: route
+                .debounce
+                .map_or_else(|| self.debounce(), Duration::from_secs),
+            max_wait: route
+                .max_wait
+                .map_or_else(|| self.max_wait(), Duration::from_secs),
+            event_types: route.event_types.clone(),
+            style: route.style.unwrap_or_else(|| self.style()),
+            templates: route.templates.clone(),
+            lifecycle: route.lifecycle.unwrap_or_else(|| self.lifecycle()),
+            route: self
+                .routes
+                .iter()
+                .find(|(_, other)| other.path == route.path)
+                .map(|(name, _)| name.clone()),
+        }
+    }
+
+    // user and pass required for the given route, env vars win over the file
+    pub fn credentials(&self, route: Option<&RouteConfig>) -> Option<(String, String)> {
+        if let Some(RouteConfig {
+            user: Some(user),
+            pass: Some(pass),
+            ..
+        }) = route
+        {
+            return Some((user.clone(), pass.clone()));
+        }
+        match (
+            std::env::var("HOOKBUFFER_USER"),
+            std::env::var("HOOKBUFFER_PASS"),
+        ) {
+            (Ok(user), Ok(pass)) => Some((user, pass)),
+            _ => self.user.clone().zip(self.pass.clone()),
+        }
+    }
+
+    // where to send webhooks for a path without a route, figured out from the shape of the path
+    pub fn target_for_path(&self, path: &str) -> Target {
+        let mut destination = Destination::from_path(path);
+        let base_url = match (&destination, &self.destination_url) {
+            (Destination::Discord, Some(url))
+                if std::env::var("HOOKBUFFER_DESTINATION_URL").is_err() =>
+            {
+                format!("{}/", url.trim_end_matches('/'))
+            }
+            _ => env::get_destination_base_url(&destination),
+        };
+        if let Destination::Matrix { access_token, .. } = &mut destination {
+            *access_token = env::get_matrix_access_token();
+        }
+        let url = destination.webhook_url(&base_url, path);
+
+        Target {
+            destination,
+            url,
+            debounce: self.debounce(),
+            max_wait: self.max_wait(),
+            event_types: Vec::new(),
+            style: self.style(),
+            templates: None,
+            lifecycle: self.lifecycle(),
+            route: None,
+        }
+    }
+}
+
+// paths the server answers itself, admin and dashboard also take everything under them
+fn is_reserved(path: &str) -> bool {
+    const RESERVED: [&str; 2] = ["healthcheck", "metrics"];
+    const RESERVED_PREFIXES: [&str; 2] = ["admin", "dashboard"];
+
+    RESERVED.contains(&path)
+        || RESERVED_PREFIXES.iter().any(|prefix| {
+            path == *prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+        port = 9000
+        destination_url = "http://discord.local"
+
+        [routes.tv]
+        path = "/tv/"
+        token = "abc"
+        url = "https://discord.com/api/webhooks/1/abc"
+        debounce = 30
+        sources = ["Sonarr"]
+        event_types = ["Download"]
+
+        [routes.movies]
+        path = "movies"
+        destination = { telegram = { chat_id = "-100" } }
+        url = "https://api.telegram.org/bot1:abc/sendMessage"
+    "#;
+
+    #[test]
+    fn parses_routes() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        let tv = config.route_for_path("tv").unwrap();
+        assert_eq!(tv.token.as_deref(), Some("abc"));
+        assert!(tv.accepts_source("sonarr"));
+        assert!(!tv.accepts_source("radarr"));
+        assert_eq!(config.route_target(tv).debounce, Duration::from_secs(30));
+        assert_eq!(config.route_target(tv).route.as_deref(), Some("tv"));
+
+        let movies = config.route_for_path("/movies").unwrap();
+        assert_eq!(
+            movies.destination,
+            Destination::Telegram {
+                chat_id: "-100".to_string()
+            }
+        );
+        assert!(config.route_for_path("music").is_none());
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = Config::parse(
+            r#"
+            user = "admin"
+
+            [routes.a]
+            path = "same"
+            url = "discord.com"
+            debounce = 0
+            templates = { content = "{{ series.title" }
+
+            [routes.b]
+            path = "same"
+            url = "https://discord.com/api/webhooks/1/abc"
+            sources = ["plex"]
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 6, "{:?}", errors);
+    }
+
+    #[test]
+    fn rejects_reserved_paths() {
+        for path in [
+            "healthcheck",
+            "/metrics",
+            "admin",
+            "admin/queues",
+            "dashboard/",
+            "dashboard/events",
+        ] {
+            let errors = Config::parse(&format!(
+                r#"
+                [routes.a]
+                path = "{}"
+                url = "https://discord.com/api/webhooks/1/abc"
+                "#,
+                path
+            ))
+            .unwrap_err();
+            assert_eq!(
+                errors,
+                vec![format!(
+                    "routes.a: path {} is reserved",
+                    path.trim_matches('/')
+                )]
+            );
+        }
+
+        // only whole segments are reserved
+        for path in ["administrators", "metrics/tv", "dashboards"] {
+            assert!(Config::parse(&format!(
+                r#"
+                [routes.a]
+                path = "{}"
+                url = "https://discord.com/api/webhooks/1/abc"
+                "#,
+                path
+            ))
+            .is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(Config::parse("prot = 9000").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn env_overrides_file() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        std::env::remove_var("HOOKBUFFER_PORT");
+        std::env::remove_var("HOOKBUFFER_DESTINATION_URL");
+        assert_eq!(config.server_port(), 9000);
+        assert_eq!(
+            config.target_for_path("api/webhooks/1/abc").url,
+            "http://discord.local/api/webhooks/1/abc"
+        );
+
+        std::env::set_var("HOOKBUFFER_PORT", "1234");
+        std::env::set_var("HOOKBUFFER_DESTINATION_URL", "http://env.local/");
+        assert_eq!(config.server_port(), 1234);
+        assert_eq!(
+            config.target_for_path("api/webhooks/1/abc").url,
+            "http://env.local/api/webhooks/1/abc"
+        );
+
+        std::env::set_var("HOOKBUFFER_PORT", "abc");
+        assert_eq!(config.server_port(), 9000);
+
+        std::env::remove_var("HOOKBUFFER_PORT");
+        std::env::remove_var("HOOKBUFFER_DESTINATION_URL");
+    }
+}
@@ -0,0 +1,221 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Response,
+    },
+    routing::get,
+    Router,
+};
+use futures::Stream;
+use serde_json::{json, Value};
+use shared_lib::error::HookbufferError;
+use shared_lib::rate_limit::RATE_LIMITER;
+
+use crate::admin::{all_queues, authorize};
+use crate::history::HISTORY;
+use crate::pause::PAUSES;
+use crate::SharedAppState;
+
+// how often the dashboard gets a fresh snapshot
+const REFRESH: Duration = Duration::from_secs(1);
+
+// a page showing pending queues, recent deliveries and rate limits, kept up to date with server-sent events
+// it uses the same credentials as the admin API
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route("/", get(page))
+        .route("/events", get(events))
+}
+
+async fn page(State(state): State<SharedAppState>, headers: HeaderMap) -> Response {
+    if let Err(e) = authorize(&state, &headers) {
+        return challenge(e);
+    }
+    Html(include_str!("dashboard.html")).into_response()
+}
+
+async fn events(
+    State(state): State<SharedAppState>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    authorize(&state, &headers).map_err(challenge)?;
+
+    let interval = tokio::time::interval(REFRESH);
+    let stream = futures::stream::unfold((state, interval), |(state, mut interval)| async {
+        interval.tick().await;
+        let event = Event::default()
+            .event("snapshot")
+            .json_data(snapshot(&state).await)
+            .unwrap_or_default();
+        Some((Ok(event), (state, interval)))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// everything the dashboard shows
+async fn snapshot(state: &SharedAppState) -> Value {
+    json!({
+        "queues": all_queues(state).await,
+        "paused": PAUSES.paused(),
+        "recent": HISTORY.recent(),
+        "failures": HISTORY.failures(),
+        "rate_limits": RATE_LIMITER.snapshot(),
+    })
+}
+
+// ask the browser for the credentials instead of just rejecting it
+fn challenge(e: HookbufferError) -> Response {
+    let unauthorized = e.status() == StatusCode::UNAUTHORIZED;
+    let mut response = e.into_response();
+    if unauthorized {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"hookbuffer\""),
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use futures::StreamExt;
+    use serial_test::serial;
+    use shared_lib::destination::Destination;
+    use shared_lib::send::SendError;
+    use shared_lib::structs::discord::{DiscordWebhook, DiscordWebhookBody};
+
+    use super::*;
+    use crate::admin::tests::{credentials, queue_event, state};
+    use crate::buffer_handler::tests::target;
+    use crate::history::DeliveryRecord;
+
+    #[tokio::test]
+    #[serial]
+    async fn asks_for_credentials() {
+        let state = state(true);
+
+        let response = page(State(state.clone()), HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Basic realm=\"hookbuffer\""
+        );
+        let response = events(State(state.clone()), HeaderMap::new())
+            .await
+            .map(IntoResponse::into_response)
+            .unwrap_or_else(|response| response);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = page(State(state), credentials()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&page).contains("<html"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn snapshot_shows_queues_and_pauses() {
+        let state = state(true);
+        let mut target = target("http://127.0.0.1:1/message".to_string());
+        target.destination = Destination::from_path("gotify/dashboard");
+        queue_event(&state, "gotify/dashboard", target).await;
+        PAUSES.pause("gotify");
+
+        let snapshot = snapshot(&state).await;
+        PAUSES.resume("gotify");
+        state.sonarr.drop_queue("gotify/dashboard").await;
+
+        assert_eq!(snapshot["paused"], json!(["gotify"]));
+        let queue = snapshot["queues"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|queue| queue["destination"] == "gotify")
+            .unwrap();
+        assert_eq!(queue["path"], "gotify/[redacted]");
+        assert_eq!(queue["source"], "sonarr");
+        assert_eq!(queue["queue_length"], 1);
+        assert_eq!(queue["paused"], true);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn snapshot_shows_deliveries_and_failures() {
+        let webhook = |batch_id: &str| {
+            DiscordWebhook::new(
+                "http://127.0.0.1:1/api/webhooks/1/abc".to_string(),
+                DiscordWebhookBody {
+                    content: "Fake Series".to_string(),
+                    embeds: Vec::new(),
+                },
+                Destination::Discord,
+                batch_id.to_string(),
+                "Download".to_string(),
+            )
+        };
+        HISTORY.record(
+            DeliveryRecord::new(&webhook("dashboard-delivered")),
+            &Ok(StatusCode::NO_CONTENT),
+        );
+        HISTORY.record(
+            DeliveryRecord::new(&webhook("dashboard-failed")),
+            &Err(SendError {
+                status: Some(StatusCode::NOT_FOUND),
+                error: "Destination responded with 404 Not Found".to_string(),
+                attempts: 1,
+                sent_parts: 0,
+            }),
+        );
+
+        let snapshot = snapshot(&state(true)).await;
+        let find = |list: &str, batch_id: &str| {
+            snapshot[list]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|delivery| delivery["batch_id"] == batch_id)
+                .cloned()
+        };
+
+        let delivered = find("recent", "dashboard-delivered").unwrap();
+        assert_eq!(delivered["delivered"], true);
+        assert_eq!(delivered["status"], 204);
+        assert!(find("failures", "dashboard-delivered").is_none());
+
+        let failed = find("failures", "dashboard-failed").unwrap();
+        assert_eq!(failed["delivered"], false);
+        assert_eq!(failed["status"], 404);
+        assert!(find("recent", "dashboard-failed").is_none());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn streams_snapshots() {
+        let response = events(State(state(true)), credentials())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body().into_data_stream();
+        let event = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event = String::from_utf8_lossy(&event);
+        let mut lines = event.lines();
+        assert_eq!(lines.next(), Some("event: snapshot"));
+        let data = lines.next().unwrap().strip_prefix("data: ").unwrap();
+        let snapshot: Value = serde_json::from_str(data).unwrap();
+        assert!(snapshot["queues"].is_array());
+        assert!(snapshot["rate_limits"]["buckets"].is_array());
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::http::StatusCode;
+use shared_lib::send::SendError;
+use shared_lib::structs::dead_letter::DeadLetter;
+use shared_lib::structs::discord::DiscordWebhook;
+
+use crate::history::{DeliveryRecord, HISTORY};
+use crate::journal::Journal;
+
+// webhooks that couldn't be delivered, shared by every source's buffer
+pub static DEAD_LETTERS: LazyLock<DeadLetters> = LazyLock::new(DeadLetters::default);
+
+// failed webhooks wait here until they're retried or deleted through the admin API
+// with a journal they use their webhook's journal id and survive a restart
+#[derive(Default)]
+pub struct DeadLetters {
+    letters: Mutex<BTreeMap<u64, DeadLetter>>,
+    // ids for dead letters of webhooks that aren't journaled
+    next_id: AtomicU64,
+}
+
+impl DeadLetters {
+    pub async fn add(
+        &self,
+        journal: Option<&Journal>,
+        journal_id: Option<u64>,
+        webhook: DiscordWebhook,
+        error: &SendError,
+    ) {
+        let id = journal_id.unwrap_or_else(|| self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let letter = DeadLetter::failed(id, now(), webhook, error, error.attempts);
+        tracing::warn!(
+            "[Dead letter] kept {} webhook {} for {}: {}",
+            letter.webhook.event_type,
+            id,
+            letter.webhook.destination.name(),
+            letter.error
+        );
+        self.insert(journal, letter).await;
+    }
+
+    // put dead letters from the journal back after a restart
+    pub fn restore(&self, restored: Vec<DeadLetter>) {
+        let mut letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+        for letter in restored {
+            self.next_id.fetch_max(letter.id, Ordering::Relaxed);
+            letters.insert(letter.id, letter);
+        }
+    }
+
+    // oldest first, with the tokens in their URLs redacted
+    pub fn list(&self) -> Vec<DeadLetter> {
+        let letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+        letters.values().map(DeadLetter::redacted).collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<DeadLetter> {
+        let letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+        letters.get(&id).cloned()
+    }
+
+    // returns false if there's no dead letter with the id
+    pub async fn remove(&self, journal: Option<&Journal>, id: u64) -> bool {
+        let removed = {
+            let mut letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+            letters.remove(&id).is_some()
+        };
+        if let (Some(journal), true) = (journal, removed) {
+            journal.record_discarded(id).await;
+        }
+        removed
+    }
+
+    // send a dead letter again, it's removed once it's delivered and kept with the new attempts added if it isn't
+    // returns None if there's no dead letter with the id
+    pub async fn retry(
+        &self,
+        journal: Option<&Journal>,
+        id: u64,
+    ) -> Option<Result<StatusCode, DeadLetter>> {
+        // taken out while it's sent so it can't be retried twice at once
+        let letter = {
+            let mut letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+            letters.remove(&id)?
+        };
+
+        let record = DeliveryRecord::new(&letter.webhook);
+        let result = shared_lib::send::send_webhook(letter.webhook.clone()).await;
+        HISTORY.record(record, &result);
+
+        match result {
+            Ok(status) => {
+                tracing::info!("[Dead letter] delivered {} on retry", id);
+                if let Some(journal) = journal {
+                    journal.record_delivered(id).await;
+                }
+                Some(Ok(status))
+            }
+            Err(error) => {
+                let letter = DeadLetter::failed(
+                    id,
+                    now(),
+                    letter.webhook,
+                    &error,
+                    letter.attempts + error.attempts,
+                );
+                tracing::warn!("[Dead letter] retrying {} failed: {}", id, letter.error);
+                self.insert(journal, letter.clone()).await;
+                Some(Err(letter))
+            }
+        }
+    }
+
+    async fn insert(&self, journal: Option<&Journal>, letter: DeadLetter) {
+        if let Some(journal) = journal {
+            journal.record_failed(&letter).await;
+        }
+        let mut letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+        letters.insert(letter.id, letter);
+    }
+}
+
+// milliseconds since the epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -1,18 +1,30 @@
-pub fn get_server_port() -> u16 {
+use shared_lib::debounce::{DEFAULT_DEBOUNCE, DEFAULT_MAX_WAIT};
+use shared_lib::destination::Destination;
+use shared_lib::retry::{DEFAULT_MAX_ATTEMPTS, DEFAULT_MAX_RETRY_TIME};
+use shared_lib::structs::discord::RenderStyle;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 8000;
+
+// HOOKBUFFER_PORT, or fallback when it isn't set or isn't a valid port
+pub fn get_server_port(fallback: u16) -> u16 {
     match std::env::var("HOOKBUFFER_PORT") {
         Ok(port) => {
             tracing::debug!("Found HOOKBUFFER_PORT: {}", port);
             match port.parse::<u16>() {
                 Ok(port) => port,
                 Err(_) => {
-                    tracing::warn!("Custom HOOKBUFFER_PORT is not a valid port number, using default port 8000");
-                    8000
+                    tracing::warn!(
+                        "Custom HOOKBUFFER_PORT is not a valid port number, using port {}",
+                        fallback
+                    );
+                    fallback
                 }
             }
         }
         Err(_) => {
-            tracing::debug!("No HOOKBUFFER_PORT found, using default port 8000");
-            8000
+            tracing::debug!("No HOOKBUFFER_PORT found, using port {}", fallback);
+            fallback
         }
     }
 }
@@ -30,6 +42,120 @@ pub fn get_destination_url() -> String {
     }
 }
 
+// base URL grouped webhooks are sent to for the given destination
+// each destination type can be pointed somewhere else with its own env var
+pub fn get_destination_base_url(destination: &Destination) -> String {
+    let var = match destination {
+        Destination::Discord => return get_destination_url(),
+        Destination::Slack => "HOOKBUFFER_SLACK_DESTINATION_URL",
+        Destination::Telegram { .. } => "HOOKBUFFER_TELEGRAM_DESTINATION_URL",
+        Destination::Matrix { .. } => "HOOKBUFFER_MATRIX_DESTINATION_URL",
+        Destination::Ntfy { .. } => "HOOKBUFFER_NTFY_DESTINATION_URL",
+        Destination::Gotify { .. } => "HOOKBUFFER_GOTIFY_DESTINATION_URL",
+        Destination::Json => "HOOKBUFFER_JSON_DESTINATION_URL",
+    };
+    match std::env::var(var) {
+        Ok(mut url) => {
+            if !url.ends_with('/') {
+                url.push('/');
+            }
+            tracing::info!("Found custom {}: {}", var, url);
+            url
+        }
+        Err(_) => destination.default_base_url().to_string(),
+    }
+}
+
+pub fn get_debounce() -> Duration {
+    get_duration_secs("HOOKBUFFER_DEBOUNCE_SECS", DEFAULT_DEBOUNCE)
+}
+
+pub fn get_max_wait() -> Duration {
+    get_duration_secs("HOOKBUFFER_MAX_WAIT_SECS", DEFAULT_MAX_WAIT)
+}
+
+pub fn get_shutdown_deadline() -> Duration {
+    get_duration_secs("HOOKBUFFER_SHUTDOWN_DEADLINE_SECS", Duration::from_secs(30))
+}
+
+pub fn get_retry_attempts() -> u32 {
+    match std::env::var("HOOKBUFFER_RETRY_ATTEMPTS").map(|attempts| attempts.parse::<u32>()) {
+        Ok(Ok(attempts)) if attempts > 0 => {
+            tracing::debug!("Found HOOKBUFFER_RETRY_ATTEMPTS: {}", attempts);
+            attempts
+        }
+        Ok(_) => {
+            tracing::warn!(
+                "Custom HOOKBUFFER_RETRY_ATTEMPTS is not a valid number of attempts, using default {}",
+                DEFAULT_MAX_ATTEMPTS
+            );
+            DEFAULT_MAX_ATTEMPTS
+        }
+        Err(_) => DEFAULT_MAX_ATTEMPTS,
+    }
+}
+
+pub fn get_retry_max_time() -> Duration {
+    get_duration_secs("HOOKBUFFER_RETRY_MAX_SECS", DEFAULT_MAX_RETRY_TIME)
+}
+
+fn get_duration_secs(var: &str, default: Duration) -> Duration {
+    match std::env::var(var).map(|secs| secs.parse::<u64>()) {
+        Ok(Ok(secs)) if secs > 0 => {
+            tracing::debug!("Found {}: {}", var, secs);
+            Duration::from_secs(secs)
+        }
+        Ok(_) => {
+            tracing::warn!(
+                "Custom {} is not a valid number of seconds, using default {}",
+                var,
+                default.as_secs()
+            );
+            default
+        }
+        Err(_) => default,
+    }
+}
+
+pub fn get_render_style() -> RenderStyle {
+    match std::env::var("HOOKBUFFER_RENDER_STYLE").map(|style| style.parse::<RenderStyle>()) {
+        Ok(Ok(style)) => style,
+        Ok(Err(e)) => {
+            tracing::warn!(
+                "Custom HOOKBUFFER_RENDER_STYLE is invalid, using full: {}",
+                e
+            );
+            RenderStyle::Full
+        }
+        Err(_) => RenderStyle::Full,
+    }
+}
+
+pub fn get_lifecycle_messages() -> bool {
+    match std::env::var("HOOKBUFFER_LIFECYCLE_MESSAGES").map(|var| var.to_lowercase()) {
+        Ok(var) if var == "true" || var == "1" => true,
+        Ok(var) if var == "false" || var == "0" || var.is_empty() => false,
+        Ok(var) => {
+            tracing::warn!(
+                "Custom HOOKBUFFER_LIFECYCLE_MESSAGES is not true or false, using false: {}",
+                var
+            );
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+pub fn get_journal_path() -> Option<String> {
+    std::env::var("HOOKBUFFER_JOURNAL_PATH")
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+pub fn get_matrix_access_token() -> Option<String> {
+    std::env::var("HOOKBUFFER_MATRIX_ACCESS_TOKEN").ok()
+}
+
 #[cfg(test)]
 mod tests {
     use serial_test::serial;
@@ -43,21 +169,22 @@ mod tests {
         #[serial]
         fn default() {
             std::env::remove_var("HOOKBUFFER_PORT");
-            assert_eq!(get_server_port(), 8000);
+            assert_eq!(get_server_port(DEFAULT_PORT), 8000);
         }
 
         #[test]
         #[serial]
         fn custom() {
             std::env::set_var("HOOKBUFFER_PORT", "1234");
-            assert_eq!(get_server_port(), 1234);
+            assert_eq!(get_server_port(DEFAULT_PORT), 1234);
         }
 
         #[test]
         #[serial]
         fn custom_invalid() {
             std::env::set_var("HOOKBUFFER_PORT", "abc");
-            assert_eq!(get_server_port(), 8000);
+            assert_eq!(get_server_port(DEFAULT_PORT), 8000);
+            assert_eq!(get_server_port(9000), 9000);
         }
     }
 
@@ -85,4 +212,62 @@ mod tests {
             assert_eq!(get_destination_url(), "https://example.com/");
         }
     }
+    mod get_destination_base_url {
+        use super::*;
+
+        #[test]
+        #[serial]
+        fn default() {
+            std::env::remove_var("HOOKBUFFER_SLACK_DESTINATION_URL");
+            assert_eq!(
+                get_destination_base_url(&Destination::Slack),
+                "https://hooks.slack.com/"
+            );
+        }
+
+        #[test]
+        #[serial]
+        fn custom_no_trailing_slash() {
+            std::env::set_var("HOOKBUFFER_SLACK_DESTINATION_URL", "https://example.com");
+            assert_eq!(
+                get_destination_base_url(&Destination::Slack),
+                "https://example.com/"
+            );
+        }
+
+        #[test]
+        #[serial]
+        fn discord() {
+            std::env::set_var("HOOKBUFFER_DESTINATION_URL", "https://example.com");
+            assert_eq!(
+                get_destination_base_url(&Destination::Discord),
+                "https://example.com/"
+            );
+        }
+    }
+
+    mod get_debounce {
+        use super::*;
+
+        #[test]
+        #[serial]
+        fn default() {
+            std::env::remove_var("HOOKBUFFER_DEBOUNCE_SECS");
+            assert_eq!(get_debounce(), DEFAULT_DEBOUNCE);
+        }
+
+        #[test]
+        #[serial]
+        fn custom() {
+            std::env::set_var("HOOKBUFFER_DEBOUNCE_SECS", "60");
+            assert_eq!(get_debounce(), Duration::from_secs(60));
+        }
+
+        #[test]
+        #[serial]
+        fn custom_invalid() {
+            std::env::set_var("HOOKBUFFER_DEBOUNCE_SECS", "0");
+            assert_eq!(get_debounce(), DEFAULT_DEBOUNCE);
+        }
+    }
 }
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::http::StatusCode;
+use serde::Serialize;
+use shared_lib::send::SendError;
+use shared_lib::structs::discord::{DiscordWebhook, DiscordWebhookBody};
+
+// how many deliveries and failures are kept for the dashboard
+const KEEP: usize = 25;
+
+// recently sent webhooks, shared by every source's buffer
+pub static HISTORY: LazyLock<History> = LazyLock::new(History::default);
+
+// a webhook that was sent, or tried to be
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryRecord {
+    // milliseconds since the epoch
+    pub at: u128,
+    pub destination: &'static str,
+    pub event_type: String,
+    pub batch_id: String,
+    // edited an earlier message instead of sending a new one
+    pub edit: bool,
+    pub body: DiscordWebhookBody,
+    pub status: u16,
+    pub delivered: bool,
+}
+
+impl DeliveryRecord {
+    // taken before sending since sending consumes the webhook
+    pub fn new(webhook: &DiscordWebhook) -> Self {
+        DeliveryRecord {
+            at: 0,
+            destination: webhook.destination.name(),
+            event_type: webhook.event_type.clone(),
+            batch_id: webhook.batch_id.clone(),
+            edit: webhook.message_id.is_some(),
+            body: webhook.body.clone(),
+            status: 0,
+            delivered: false,
+        }
+    }
+}
+
+// the last few deliveries and failures, newest first
+#[derive(Default)]
+pub struct History {
+    recent: Mutex<VecDeque<DeliveryRecord>>,
+    failures: Mutex<VecDeque<DeliveryRecord>>,
+}
+
+impl History {
+    pub fn record(&self, mut record: DeliveryRecord, result: &Result<StatusCode, SendError>) {
+        record.at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        record.delivered = result.is_ok();
+        record.status = match result {
+            Ok(status) => status.as_u16(),
+            Err(error) => error.status().as_u16(),
+        };
+
+        let list = match record.delivered {
+            true => &self.recent,
+            false => &self.failures,
+        };
+        let mut list = list.lock().unwrap_or_else(|e| e.into_inner());
+        list.push_front(record);
+        list.truncate(KEEP);
+    }
+
+    pub fn recent(&self) -> Vec<DeliveryRecord> {
+        let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        recent.iter().cloned().collect()
+    }
+
+    pub fn failures(&self) -> Vec<DeliveryRecord> {
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.iter().cloned().collect()
+    }
+}
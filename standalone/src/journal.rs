@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared_lib::source::Source;
+use shared_lib::structs::dead_letter::DeadLetter;
+use shared_lib::structs::discord::DiscordWebhook;
+use tokio::sync::oneshot;
+
+use crate::buffer_handler::Target;
+
+// one line of the journal file
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record {
+    // an accepted event waiting in a queue
+    Event {
+        id: u64,
+        source: String,
+        path: String,
+        target: Target,
+        event: Value,
+    },
+    // these events were turned into webhooks and don't need replaying anymore
+    Flushed {
+        ids: Vec<u64>,
+    },
+    // a rendered webhook waiting to be delivered
+    Webhook {
+        id: u64,
+        webhook: DiscordWebhook,
+    },
+    Delivered {
+        id: u64,
+    },
+    // delivering the webhook failed, it's kept as a dead letter until it's retried or deleted
+    Failed {
+        id: u64,
+        failed_at: u64,
+        status: Option<u16>,
+        error: String,
+        attempts: u32,
+        // parts of a split batch that were delivered, see DiscordWebhook::sent_parts
+        #[serde(default)]
+        sent_parts: usize,
+    },
+    // a dead letter that was deleted without being delivered
+    Discarded {
+        id: u64,
+    },
+}
+
+// an event that was accepted but never flushed before the last shutdown
+#[derive(Debug, Clone)]
+pub struct JournaledEvent {
+    pub id: u64,
+    pub source: String,
+    pub path: String,
+    pub target: Target,
+    pub event: Value,
+}
+
+// everything still pending when the journal was opened
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub events: Vec<JournaledEvent>,
+    pub webhooks: Vec<(u64, DiscordWebhook)>,
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+// append-only record of accepted events and outgoing webhooks so a restart doesn't lose them
+pub struct Journal {
+    // records go to the writer thread, see write_batches
+    writes: mpsc::Sender<PendingWrite>,
+    next_id: AtomicU64,
+}
+
+impl Journal {
+    // read whatever is still pending from the journal at path, then compact the file down to just that
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(Journal, Replay)> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut events: BTreeMap<u64, Record> = BTreeMap::new();
+        let mut webhooks: BTreeMap<u64, Record> = BTreeMap::new();
+        // the webhook and latest failure of each dead letter
+        let mut dead: BTreeMap<u64, (Record, Record)> = BTreeMap::new();
+        let mut max_id = 0;
+        if let Ok(file) = File::open(&path) {
+            for (line_number, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<Record>(&line) {
+                    Ok(record @ Record::Event { id, .. }) => {
+                        max_id = max_id.max(id);
+                        events.insert(id, record);
+                    }
+                    Ok(Record::Flushed { ids }) => {
+                        for id in ids {
+                            events.remove(&id);
+                        }
+                    }
+                    Ok(record @ Record::Webhook { id, .. }) => {
+                        max_id = max_id.max(id);
+                        webhooks.insert(id, record);
+                    }
+                    Ok(Record::Delivered { id }) => {
+                        webhooks.remove(&id);
+                        dead.remove(&id);
+                    }
+                    Ok(failed @ Record::Failed { id, .. }) => {
+                        if let Some(webhook) = webhooks.remove(&id) {
+                            dead.insert(id, (webhook, failed));
+                        } else if let Some((_, last_failure)) = dead.get_mut(&id) {
+                            *last_failure = failed;
+                        }
+                    }
+                    Ok(Record::Discarded { id }) => {
+                        dead.remove(&id);
+                    }
+                    // most likely a write that was cut off by a crash
+                    Err(e) => tracing::warn!(
+                        "[Journal] skipping unreadable line {} of {}: {}",
+                        line_number + 1,
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        // rewrite the file with only the pending records so it doesn't grow forever
+        let compacted_path = path.with_extension("compacting");
+        {
+            let mut compacted = File::create(&compacted_path)?;
+            let dead_records = dead
+                .values()
+                .flat_map(|(webhook, failed)| [webhook, failed]);
+            for record in events.values().chain(webhooks.values()).chain(dead_records) {
+                writeln!(compacted, "{}", serde_json::to_string(record)?)?;
+            }
+            compacted.sync_all()?;
+        }
+        std::fs::rename(&compacted_path, &path)?;
+
+        let mut replay = Replay::default();
+        for record in events.into_values().chain(webhooks.into_values()) {
+            match record {
+                Record::Event {
+                    id,
+                    source,
+                    path,
+                    target,
+                    event,
+                } => replay.events.push(JournaledEvent {
+                    id,
+                    source,
+                    path,
+                    target,
+                    event,
+                }),
+                Record::Webhook { id, webhook } => replay.webhooks.push((id, webhook)),
+                _ => {}
+            }
+        }
+        for (webhook, failed) in dead.into_values() {
+            if let (
+                Record::Webhook { id, mut webhook },
+                Record::Failed {
+                    failed_at,
+                    status,
+                    error,
+                    attempts,
+                    sent_parts,
+                    ..
+                },
+            ) = (webhook, failed)
+            {
+                webhook.sent_parts = sent_parts;
+                replay.dead_letters.push(DeadLetter {
+                    id,
+                    failed_at,
+                    webhook,
+                    status,
+                    error,
+                    attempts,
+                });
+            }
+        }
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+        let (writes, pending) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("hookbuffer-journal".to_string())
+            .spawn(move || write_batches(path, file, pending))?;
+        let journal = Journal {
+            writes,
+            next_id: AtomicU64::new(max_id + 1),
+        };
+        Ok((journal, replay))
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // write the records in one go and wait for them to hit the disk
+    // failures are only logged, a broken journal shouldn't stop webhooks from being delivered
+    async fn append(&self, records: &[Record]) {
+        let mut lines = String::new();
+        for record in records {
+            match serde_json::to_string(record) {
+                Ok(line) => {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+                Err(e) => tracing::error!("[Journal] couldn't serialize record: {}", e),
+            }
+        }
+
+        let (done, written) = oneshot::channel();
+        if self.writes.send(PendingWrite { lines, done }).is_err() {
+            tracing::error!("[Journal] the writer thread has stopped");
+            return;
+        }
+        let _ = written.await;
+    }
+
+    // returns the id to pass to record_flush once the event has been sent
+    pub async fn record_event<S: Source>(
+        &self,
+        path: &str,
+        target: &Target,
+        event: &S::Event,
+    ) -> u64 {
+        let id = self.next_id();
+        match serde_json::to_value(event) {
+            Ok(event) => {
+                self.append(&[Record::Event {
+                    id,
+                    source: S::NAME.to_string(),
+                    path: path.to_string(),
+                    target: target.clone(),
+                    event,
+                }])
+                .await
+            }
+            Err(e) => tracing::error!("[Journal] couldn't serialize event: {}", e),
+        }
+        id
+    }
+
+    // swap the flushed events for the webhooks they were rendered into
+    // returns the id of each webhook to pass to record_delivered
+    pub async fn record_flush(&self, event_ids: Vec<u64>, webhooks: &[DiscordWebhook]) -> Vec<u64> {
+        let mut records = Vec::new();
+        let mut webhook_ids = Vec::new();
+        for webhook in webhooks {
+            let id = self.next_id();
+            webhook_ids.push(id);
+            records.push(Record::Webhook {
+                id,
+                webhook: webhook.clone(),
+            });
+        }
+        records.push(Record::Flushed { ids: event_ids });
+        self.append(&records).await;
+        webhook_ids
+    }
+
+    pub async fn record_delivered(&self, id: u64) {
+        self.append(&[Record::Delivered { id }]).await;
+    }
+
+    // the dead letter's id has to be the id of its webhook in the journal
+    pub async fn record_failed(&self, letter: &DeadLetter) {
+        self.append(&[Record::Failed {
+            id: letter.id,
+            failed_at: letter.failed_at,
+            status: letter.status,
+            error: letter.error.clone(),
+            attempts: letter.attempts,
+            sent_parts: letter.webhook.sent_parts,
+        }])
+        .await;
+    }
+
+    pub async fn record_discarded(&self, id: u64) {
+        self.append(&[Record::Discarded { id }]).await;
+    }
+}
+
+// lines waiting for the writer thread, done is sent once they're on disk
+struct PendingWrite {
+    lines: String,
+    done: oneshot::Sender<()>,
+}
+
+// runs on its own thread so the blocking writes stay off the runtime
+// everything that queued up while the last batch was syncing is written and synced together
+fn write_batches(path: PathBuf, mut file: File, pending: mpsc::Receiver<PendingWrite>) {
+    while let Ok(first) = pending.recv() {
+        let batch: Vec<PendingWrite> = std::iter::once(first).chain(pending.try_iter()).collect();
+        let lines: String = batch.iter().map(|write| write.lines.as_str()).collect();
+        if let Err(e) = file
+            .write_all(lines.as_bytes())
+            .and_then(|_| file.sync_data())
+        {
+            tracing::error!("[Journal] couldn't write to {}: {}", path.display(), e);
+        }
+        for write in batch {
+            let _ = write.done.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use shared_lib::destination::Destination;
+    use shared_lib::send::SendError;
+    use shared_lib::source::{build_webhook, Sonarr};
+    use shared_lib::structs::discord::{DiscordWebhookBody, RenderStyle};
+    use tokio::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn replays_until_delivered() {
+        let path = std::env::temp_dir().join(format!(
+            "hookbuffer-journal-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let target = Target {
+            destination: Destination::Discord,
+            url: "http://localhost/api/webhooks/1/abc".to_string(),
+            debounce: Duration::from_secs(15),
+            max_wait: Duration::from_secs(300),
+            event_types: Vec::new(),
+            style: RenderStyle::Full,
+            templates: None,
+            lifecycle: false,
+            route: None,
+        };
+        let event = Sonarr::parse(json!({
+            "eventType": "Download",
+            "series": { "id": 1, "title": "Fake Series" },
+            "episodes": [{
+                "episodeNumber": 1,
+                "seasonNumber": 1,
+                "seriesId": 1,
+                "title": "Fake Episode"
+            }]
+        }))
+        .unwrap();
+
+        // an accepted event is replayed
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert!(replay.events.is_empty());
+        let event_id = journal
+            .record_event::<Sonarr>("api/webhooks/1/abc", &target, &event)
+            .await;
+        drop(journal);
+
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert_eq!(replay.events.len(), 1);
+        assert_eq!(replay.events[0].id, event_id);
+        assert_eq!(replay.events[0].source, "sonarr");
+
+        // once flushed only the webhook is replayed
+        let webhook = build_webhook::<Sonarr>(
+            target.url.clone(),
+            &target.destination,
+            vec![event],
+            0,
+            target.style,
+            target.templates.as_ref(),
+        );
+        let webhook_ids = journal.record_flush(vec![event_id], &[webhook]).await;
+        drop(journal);
+
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert!(replay.events.is_empty());
+        assert_eq!(replay.webhooks.len(), 1);
+        assert_eq!(replay.webhooks[0].0, webhook_ids[0]);
+
+        // a failed webhook is replayed as a dead letter instead
+        let error = SendError {
+            status: Some(StatusCode::NOT_FOUND),
+            error: "Destination responded with 404 Not Found: Unknown Webhook".to_string(),
+            attempts: 1,
+            sent_parts: 0,
+        };
+        let letter = DeadLetter::failed(webhook_ids[0], 0, replay.webhooks[0].1.clone(), &error, 3);
+        journal.record_failed(&letter).await;
+        drop(journal);
+
+        let (journal, replay) = Journal::open(&path).unwrap();
+        assert!(replay.webhooks.is_empty());
+        assert_eq!(replay.dead_letters.len(), 1);
+        assert_eq!(replay.dead_letters[0].id, webhook_ids[0]);
+        assert_eq!(replay.dead_letters[0].status, Some(404));
+        assert_eq!(replay.dead_letters[0].error, error.error);
+        assert_eq!(replay.dead_letters[0].attempts, 3);
+
+        // and once it's retried and delivered nothing is
+        journal.record_delivered(webhook_ids[0]).await;
+        drop(journal);
+
+        let (_, replay) = Journal::open(&path).unwrap();
+        assert!(replay.events.is_empty());
+        assert!(replay.webhooks.is_empty());
+        assert!(replay.dead_letters.is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn concurrent_records_are_all_written() {
+        let path = std::env::temp_dir().join(format!(
+            "hookbuffer-journal-batch-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // every delivery is waited on together, so they're written in as few batches as the writer can manage
+        let (journal, _) = Journal::open(&path).unwrap();
+        let webhook = DiscordWebhook::new(
+            "http://localhost/api/webhooks/1/abc".to_string(),
+            DiscordWebhookBody {
+                content: "Imported: Fake Series Season 01".to_string(),
+                embeds: Vec::new(),
+            },
+            Destination::Discord,
+            "sonarr-abc-123".to_string(),
+            "Download".to_string(),
+        );
+        let ids = journal.record_flush(Vec::new(), &vec![webhook; 50]).await;
+        futures::future::join_all(ids[..25].iter().map(|&id| journal.record_delivered(id))).await;
+        drop(journal);
+
+        let (_, replay) = Journal::open(&path).unwrap();
+        let replayed: Vec<u64> = replay.webhooks.iter().map(|(id, _)| *id).collect();
+        assert_eq!(replayed, ids[25..]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::http::StatusCode;
+use shared_lib::destination::Destination;
+use shared_lib::send::{post_discord_message, send_webhook, SendError};
+use shared_lib::structs::discord::{DiscordWebhook, DiscordWebhookBody};
+use tokio::time::{Duration, Instant};
+
+// grabs that still haven't been fully imported after this long are forgotten
+const TRACK_FOR: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// a posted grab message that gets edited as its downloads are imported
+struct TrackedGrab {
+    url: String,
+    message_id: String,
+    batch_id: String,
+    body: DiscordWebhookBody,
+    // items each download id still has to import
+    remaining: HashMap<String, usize>,
+    total: usize,
+    imported: usize,
+    posted: Instant,
+}
+
+#[derive(Default)]
+struct LifecycleState {
+    grabs: HashMap<u64, TrackedGrab>,
+    // the grab each download id belongs to
+    downloads: HashMap<String, u64>,
+    next_id: u64,
+}
+
+// posts one Discord message per grab batch and edits it to show import progress, ex: "(12/12 imported)"
+// grabs and imports are matched by the download client's id for the release
+#[derive(Default)]
+pub struct Lifecycle {
+    state: Mutex<LifecycleState>,
+}
+
+impl Lifecycle {
+    // send the message for a grab batch and remember it so the imports can update it
+    // downloads are the (download id, item count) of every grab in the batch
+    // returns the status Discord responded with
+    pub async fn post_grab(
+        &self,
+        webhook: DiscordWebhook,
+        downloads: Vec<(String, usize)>,
+    ) -> Result<StatusCode, SendError> {
+        let mut pages = webhook.body.clone().paginate();
+        // a batch split over several messages can't be edited as one
+        if pages.len() != 1 || downloads.is_empty() {
+            return send_webhook(webhook).await;
+        }
+        let body = pages.remove(0);
+
+        let (status, message_id) = post_discord_message(webhook.url.clone(), body.clone()).await?;
+        tracing::info!("[Lifecycle] tracking grab message {}", message_id);
+        self.track(webhook.url, message_id, webhook.batch_id, body, downloads);
+        Ok(status)
+    }
+
+    fn track(
+        &self,
+        url: String,
+        message_id: String,
+        batch_id: String,
+        body: DiscordWebhookBody,
+        downloads: Vec<(String, usize)>,
+    ) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.forget_expired();
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let mut remaining: HashMap<String, usize> = HashMap::new();
+        for (download_id, count) in downloads {
+            *remaining.entry(download_id.clone()).or_default() += count;
+            state.downloads.insert(download_id, id);
+        }
+        state.grabs.insert(
+            id,
+            TrackedGrab {
+                url,
+                message_id,
+                batch_id,
+                body,
+                total: remaining.values().sum(),
+                remaining,
+                imported: 0,
+                posted: Instant::now(),
+            },
+        );
+    }
+
+    pub fn is_tracked(&self, download_id: &str) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.downloads.contains_key(download_id)
+    }
+
+    // record imports for tracked downloads, returns an edit for every grab message they changed
+    pub fn imported(
+        &self,
+        downloads: impl IntoIterator<Item = (String, usize)>,
+    ) -> Vec<DiscordWebhook> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut changed = Vec::new();
+        for (download_id, count) in downloads {
+            let Some(&id) = state.downloads.get(&download_id) else {
+                continue;
+            };
+            let Some(grab) = state.grabs.get_mut(&id) else {
+                continue;
+            };
+            // reimports of something that already finished don't count twice
+            let remaining = grab.remaining.entry(download_id).or_default();
+            let count = count.min(*remaining);
+            *remaining -= count;
+            grab.imported += count;
+            if !changed.contains(&id) {
+                changed.push(id);
+            }
+        }
+
+        let mut edits = Vec::new();
+        for id in changed {
+            let Some(grab) = state.grabs.get(&id) else {
+                continue;
+            };
+            let mut edit = DiscordWebhook::new(
+                grab.url.clone(),
+                grab.body.with_progress(grab.imported, grab.total),
+                Destination::Discord,
+                grab.batch_id.clone(),
+                "Grab".to_string(),
+            );
+            edit.message_id = Some(grab.message_id.clone());
+            edits.push(edit);
+
+            if grab.imported >= grab.total {
+                state.forget(id);
+            }
+        }
+        edits
+    }
+}
+
+impl LifecycleState {
+    fn forget(&mut self, id: u64) {
+        if let Some(grab) = self.grabs.remove(&id) {
+            for download_id in grab.remaining.keys() {
+                self.downloads.remove(download_id);
+            }
+        }
+    }
+
+    fn forget_expired(&mut self) {
+        let expired = self
+            .grabs
+            .iter()
+            .filter(|(_, grab)| grab.posted.elapsed() > TRACK_FOR)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in expired {
+            self.forget(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> DiscordWebhookBody {
+        DiscordWebhookBody {
+            content: "Grabbed: Fake Series Season 01".to_string(),
+            embeds: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn edits_grab_as_imports_arrive() {
+        let lifecycle = Lifecycle::default();
+        lifecycle.track(
+            "http://localhost/api/webhooks/1/abc".to_string(),
+            "42".to_string(),
+            "batch".to_string(),
+            body(),
+            vec![("pack".to_string(), 2), ("single".to_string(), 1)],
+        );
+        assert!(lifecycle.is_tracked("pack"));
+        assert!(!lifecycle.is_tracked("other"));
+
+        let edits = lifecycle.imported(vec![("pack".to_string(), 1), ("pack".to_string(), 1)]);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].message_id.as_deref(), Some("42"));
+        assert_eq!(
+            edits[0].body.content,
+            "Grabbed: Fake Series Season 01 (2/3 imported)"
+        );
+
+        let edits = lifecycle.imported(vec![("single".to_string(), 1)]);
+        assert_eq!(
+            edits[0].body.content,
+            "Grabbed: Fake Series Season 01 (3/3 imported)"
+        );
+        // finished grabs are forgotten
+        assert!(!lifecycle.is_tracked("pack"));
+    }
+}
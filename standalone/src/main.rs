@@ -1,14 +1,19 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
-    extract::{Path, State},
-    http::{HeaderMap, Request, StatusCode, Uri},
+    body::Bytes,
+    extract::{Path, RawQuery, State},
+    http::{header, HeaderMap, Request, StatusCode, Uri},
     middleware::Next,
     response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use metrics_exporter_prometheus::PrometheusHandle;
 use serde_json::Value;
+use subtle::ConstantTimeEq;
 use tower_http::compression::{
     predicate::{DefaultPredicate, NotForContentType, Predicate},
     CompressionLayer,
@@ -19,12 +24,40 @@ use tracing::{level_filters, Span};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::sonarr_handler::SonarrHandler;
+use shared_lib::error::HookbufferError;
+use shared_lib::source::{Lidarr, Radarr, Sonarr, Source};
 
+use crate::buffer_handler::{deliver, BufferHandler, Target};
+use crate::config::{Config, RouteConfig};
+use crate::dead_letter::DEAD_LETTERS;
+use crate::journal::Journal;
+use crate::pause::PAUSES;
+
+mod admin;
+mod buffer_handler;
+mod config;
+mod dashboard;
+mod dead_letter;
 mod env;
-mod sonarr_handler;
+mod history;
+mod journal;
+mod lifecycle;
+mod pause;
+mod telemetry;
+
+struct AppState {
+    config: Config,
+    // set once a shutdown signal arrives, new webhooks are sent right away instead of buffered
+    draining: AtomicBool,
+    metrics: PrometheusHandle,
+    // used by the admin API to record retried and deleted dead letters
+    journal: Option<Arc<Journal>>,
+    sonarr: BufferHandler<Sonarr>,
+    radarr: BufferHandler<Radarr>,
+    lidarr: BufferHandler<Lidarr>,
+}
 
-type SharedAppState = Arc<SonarrHandler>;
+type SharedAppState = Arc<AppState>;
 
 #[derive(Debug, Clone)]
 struct RequestUri(Uri);
@@ -39,6 +72,76 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(errors) => {
+            tracing::error!("Invalid config, found {} problems:", errors.len());
+            for error in errors {
+                tracing::error!("  {}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+    for (name, route) in &config.routes {
+        tracing::info!(
+            "[Route] {} /{} -> {:?}",
+            name,
+            route.path.trim_matches('/'),
+            route.destination
+        );
+    }
+    let server_port = config.server_port();
+    config.retry_policy().install();
+
+    let journal = config
+        .journal_path()
+        .map(|path| match Journal::open(&path) {
+            Ok((journal, replay)) => {
+                tracing::info!(
+                    "[Journal] opened {}, replaying {} events and {} webhooks, {} dead letters",
+                    path,
+                    replay.events.len(),
+                    replay.webhooks.len(),
+                    replay.dead_letters.len()
+                );
+                (Arc::new(journal), replay)
+            }
+            Err(e) => {
+                tracing::error!("[Journal] couldn't open {}: {}", path, e);
+                std::process::exit(1);
+            }
+        });
+
+    let shutdown_deadline = config.shutdown_deadline();
+    let state = SharedAppState::new(AppState {
+        config,
+        draining: AtomicBool::new(false),
+        metrics: telemetry::install(),
+        journal: journal.as_ref().map(|(journal, _)| journal.clone()),
+        sonarr: BufferHandler::new(journal.as_ref().map(|(journal, _)| journal.clone())),
+        radarr: BufferHandler::new(journal.as_ref().map(|(journal, _)| journal.clone())),
+        lidarr: BufferHandler::new(journal.as_ref().map(|(journal, _)| journal.clone())),
+    });
+
+    if let Some((journal, replay)) = journal {
+        DEAD_LETTERS.restore(replay.dead_letters);
+        for event in replay.events {
+            match event.source.as_str() {
+                "sonarr" => state.sonarr.restore(event).await,
+                "radarr" => state.radarr.restore(event).await,
+                "lidarr" => state.lidarr.restore(event).await,
+                source => tracing::warn!("[Journal] skipping event from unknown source {}", source),
+            }
+        }
+        tokio::spawn(async move {
+            let webhooks = replay
+                .webhooks
+                .into_iter()
+                .map(|(journal_id, webhook)| (Some(journal_id), webhook));
+            deliver(Some(&journal), webhooks).await;
+        });
+    }
+
     let predicate = DefaultPredicate::new().and(NotForContentType::new("application/json"));
     let compression_layer = CompressionLayer::new().gzip(true).compress_when(predicate);
 
@@ -69,7 +172,7 @@ async fn main() {
                     format!("{:.2}ms", milliseconds)
                 };
 
-                if url == "/healthcheck" {
+                if url == "/healthcheck" || url == "/metrics" || url == "/dashboard/events" {
                     tracing::trace!("{} {} {}", url, status, latency);
                     return;
                 }
@@ -79,59 +182,199 @@ async fn main() {
         ))
         .layer(compression_layer)
         .route("/healthcheck", get(health_check))
+        .route("/metrics", get(metrics))
+        .nest("/admin", admin::router())
+        .nest("/dashboard", dashboard::router())
         .route("/{*path}", post(handle_post))
-        .with_state(SharedAppState::default());
+        .with_state(state.clone());
 
-    let server_port = env::get_server_port();
     tracing::info!("Server started at localhost:{}", server_port);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", server_port))
         .await
         .unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown(state, shutdown_deadline))
+        .await
+        .unwrap();
 }
 
 async fn handle_post(
-    State(sonarr_handler): State<SharedAppState>,
-    Path(path): Path<String>, // Must come before other extractors
+    state: State<SharedAppState>,
+    path: Path<String>, // Must come before other extractors
+    query: RawQuery,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, HookbufferError> {
+    let result = receive(state, path, query, headers, body).await;
+    match &result {
+        Ok(_) => metrics::counter!("hookbuffer_requests_accepted_total").increment(1),
+        Err(e) => metrics::counter!("hookbuffer_requests_rejected_total", "reason" => e.code())
+            .increment(1),
+    }
+    result
+}
+
+// check a webhook and pass it on to the buffer for its application
+async fn receive(
+    State(state): State<SharedAppState>,
+    Path(path): Path<String>,
+    RawQuery(query): RawQuery,
     headers: HeaderMap,
-    Json(body): Json<Value>,
-) -> impl IntoResponse {
+    body: Bytes,
+) -> Result<Response, HookbufferError> {
+    let route = state.config.route_for_path(&path);
+
     // Basic auth check
-    if let (Ok(user), Ok(pass)) = (
-        std::env::var("HOOKBUFFER_USER"),
-        std::env::var("HOOKBUFFER_PASS"),
-    ) {
-        if let Err(response) = shared_lib::auth::check_auth(user, pass, &headers) {
-            return response;
+    if let Some((user, pass)) = state.config.credentials(route) {
+        shared_lib::auth::check_auth(user, pass, &headers)?;
+    }
+
+    // routes with a token need it passed as ?token=<token>
+    if let Some(token) = route.and_then(|route| route.token.as_deref()) {
+        if !token_matches(query.as_deref(), token) {
+            tracing::warn!("Received request with an invalid token for /{}", path);
+            return Err(HookbufferError::Unauthorized("Invalid token".to_string()));
         }
     }
 
+    let body: Value = serde_json::from_slice(&body).map_err(|e| {
+        tracing::warn!("Received a body that isn't JSON for /{}: {}", path, e);
+        HookbufferError::InvalidJson(e.to_string())
+    })?;
+
+    let mut target = match route {
+        Some(route) => state.config.route_target(route),
+        None => state.config.target_for_path(&path),
+    };
+    if state.draining.load(Ordering::SeqCst) {
+        target.debounce = Duration::ZERO;
+    }
+
     // User-Agent verification
     match headers.get("User-Agent").and_then(|h| h.to_str().ok()) {
-        Some(agent) if agent.starts_with("Sonarr") => {
-            let handler = Arc::clone(&sonarr_handler);
-            tokio::spawn(async move {
-                handler.handle(path.clone(), body).await;
-            });
-
-            (
-                StatusCode::OK,
-                Json("Processing and forwarding Sonarr episode webhook"),
-            )
-                .into_response()
+        Some(agent) if agent.starts_with(Sonarr::USER_AGENT) => {
+            buffer(&state.sonarr, route, path, target, body).await
+        }
+        Some(agent) if agent.starts_with(Radarr::USER_AGENT) => {
+            buffer(&state.radarr, route, path, target, body).await
+        }
+        Some(agent) if agent.starts_with(Lidarr::USER_AGENT) => {
+            buffer(&state.lidarr, route, path, target, body).await
         }
         _ => {
             tracing::warn!("Received unsupported User-Agent");
-            (
-                StatusCode::BAD_REQUEST,
-                Json("Received unsupported User-Agent"),
-            )
-                .into_response()
+            Err(HookbufferError::UnsupportedUserAgent)
         }
     }
 }
 
-async fn health_check() -> &'static str {
-    "OK"
+// whether the query has the route's token, compared in constant time so it can't be guessed byte by byte
+fn token_matches(query: Option<&str>, token: &str) -> bool {
+    let provided = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .find_map(|(key, value)| (key == "token").then_some(value));
+    match provided {
+        Some(provided) => bool::from(provided.as_bytes().ct_eq(token.as_bytes())),
+        None => false,
+    }
+}
+
+// check the webhook and hand it to the source's buffer, the sender only waits for the checks
+async fn buffer<S: Source>(
+    handler: &BufferHandler<S>,
+    route: Option<&RouteConfig>,
+    path: String,
+    target: Target,
+    body: Value,
+) -> Result<Response, HookbufferError> {
+    if route.is_some_and(|route| !route.accepts_source(S::NAME)) {
+        tracing::warn!("Route /{} doesn't accept {} webhooks", path, S::USER_AGENT);
+        return Err(HookbufferError::SourceNotAccepted(
+            S::USER_AGENT.to_string(),
+        ));
+    }
+
+    let message = handler.handle(path, target, body).await?;
+    Ok((StatusCode::OK, Json(message)).into_response())
+}
+
+// counters and queue depths in the Prometheus text format
+async fn metrics(State(state): State<SharedAppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+async fn health_check(State(state): State<SharedAppState>) -> (StatusCode, &'static str) {
+    if state.draining.load(Ordering::SeqCst) {
+        (StatusCode::SERVICE_UNAVAILABLE, "Draining")
+    } else {
+        (StatusCode::OK, "OK")
+    }
+}
+
+// wait for ctrl-c or SIGTERM, then send everything still buffered before letting the server stop
+// the health check reports not ready while this runs so load balancers stop sending traffic
+async fn shutdown(state: SharedAppState, deadline: Duration) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for ctrl-c");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!(
+        "[Shutdown] sending buffered webhooks, waiting up to {}s",
+        deadline.as_secs()
+    );
+    state.draining.store(true, Ordering::SeqCst);
+    // paused destinations are sent to as well, otherwise their queues would hold up shutting down
+    PAUSES.release();
+
+    let flush = async {
+        tokio::join!(
+            state.sonarr.drain(),
+            state.radarr.drain(),
+            state.lidarr.drain()
+        );
+    };
+    match tokio::time::timeout(deadline, flush).await {
+        Ok(()) => tracing::info!("[Shutdown] all buffered webhooks flushed"),
+        Err(_) => tracing::warn!(
+            "[Shutdown] deadline passed before all buffered webhooks were sent, {}",
+            match state.config.journal_path() {
+                Some(_) => "the rest will be sent from the journal on the next start",
+                None => "the rest are lost",
+            }
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_tokens() {
+        assert!(token_matches(Some("token=abc"), "abc"));
+        assert!(token_matches(Some("wait=true&token=a%2Bb%3D"), "a+b="));
+        assert!(!token_matches(Some("token=abd"), "abc"));
+        assert!(!token_matches(Some("token=ab"), "abc"));
+        assert!(!token_matches(Some("other=abc"), "abc"));
+        assert!(!token_matches(None, "abc"));
+    }
 }
@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+use tokio::sync::Notify;
+
+// destinations the admin API has paused, shared by every source's buffer
+pub static PAUSES: LazyLock<Pauses> = LazyLock::new(Pauses::default);
+
+// while a destination is paused its queues keep buffering instead of being sent,
+// everything waiting goes out once it's resumed. pauses don't survive a restart
+#[derive(Default)]
+pub struct Pauses {
+    paused: Mutex<BTreeSet<String>>,
+    resumed: Notify,
+    // set when shutting down, nothing is held back anymore so it can all be sent before the deadline
+    released: AtomicBool,
+}
+
+impl Pauses {
+    // returns false if the destination was already paused
+    pub fn pause(&self, destination: &str) -> bool {
+        let mut paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        paused.insert(destination.to_string())
+    }
+
+    // returns false if the destination wasn't paused
+    pub fn resume(&self, destination: &str) -> bool {
+        let resumed = {
+            let mut paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+            paused.remove(destination)
+        };
+        self.resumed.notify_waiters();
+        resumed
+    }
+
+    pub fn is_paused(&self, destination: &str) -> bool {
+        let paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        paused.contains(destination)
+    }
+
+    pub fn paused(&self) -> Vec<String> {
+        let paused = self.paused.lock().unwrap_or_else(|e| e.into_inner());
+        paused.iter().cloned().collect()
+    }
+
+    // stop holding webhooks for paused destinations, for shutting down
+    pub fn release(&self) {
+        self.released.store(true, Ordering::SeqCst);
+        self.resumed.notify_waiters();
+    }
+
+    // wait until sending to the destination isn't paused, returns right away if it isn't
+    pub async fn wait(&self, destination: &str) {
+        loop {
+            // registered before checking so a resume in between isn't missed
+            let resumed = self.resumed.notified();
+            tokio::pin!(resumed);
+            resumed.as_mut().enable();
+
+            if self.released.load(Ordering::SeqCst) || !self.is_paused(destination) {
+                return;
+            }
+            tracing::info!("[Paused] holding {} webhooks until resumed", destination);
+            resumed.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_until_resumed() {
+        let pauses = Arc::new(Pauses::default());
+        assert!(pauses.pause("discord"));
+        assert!(!pauses.pause("discord"));
+        assert_eq!(pauses.paused(), vec!["discord".to_string()]);
+
+        // other destinations aren't held up
+        pauses.wait("slack").await;
+
+        let waiting = tokio::spawn({
+            let pauses = Arc::clone(&pauses);
+            async move { pauses.wait("discord").await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        assert!(pauses.resume("discord"));
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!pauses.resume("discord"));
+    }
+
+    #[tokio::test]
+    async fn releasing_stops_waiting() {
+        let pauses = Arc::new(Pauses::default());
+        pauses.pause("discord");
+
+        let waiting = tokio::spawn({
+            let pauses = Arc::clone(&pauses);
+            async move { pauses.wait("discord").await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        pauses.release();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        // still listed as paused, it's just not waited on anymore
+        assert!(pauses.is_paused("discord"));
+        pauses.wait("discord").await;
+    }
+}
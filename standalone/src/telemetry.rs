@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+// how long events wait in a queue before it's sent, in seconds
+const BUFFER_BUCKETS: &[f64] = &[
+    1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+];
+// how long a rate limited request waits before it's retried, in seconds
+const BACKOFF_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0];
+
+// install the recorder every metric in hookbuffer reports to
+// returns the handle /metrics renders from
+pub fn install() -> PrometheusHandle {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full("hookbuffer_buffer_seconds".to_string()),
+            BUFFER_BUCKETS,
+        )
+        .and_then(|builder| {
+            builder.set_buckets_for_metric(
+                Matcher::Full("hookbuffer_rate_limit_backoff_seconds".to_string()),
+                BACKOFF_BUCKETS,
+            )
+        })
+        .and_then(|builder| builder.install_recorder())
+        .expect("failed to install the metrics recorder");
+
+    describe();
+
+    // histograms hold on to their samples until upkeep runs
+    let upkeep = handle.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            upkeep.run_upkeep();
+        }
+    });
+
+    handle
+}
+
+fn describe() {
+    describe_counter!(
+        "hookbuffer_requests_accepted_total",
+        "Webhooks that passed validation"
+    );
+    describe_counter!(
+        "hookbuffer_requests_rejected_total",
+        "Webhooks rejected with an error, by error code"
+    );
+    describe_counter!(
+        "hookbuffer_events_received_total",
+        "Events parsed from incoming webhooks, by source and event type"
+    );
+    describe_counter!(
+        "hookbuffer_groups_flushed_total",
+        "Groups of buffered events rendered into a message"
+    );
+    describe_counter!(
+        "hookbuffer_webhooks_sent_total",
+        "Webhooks delivered, by destination and status"
+    );
+    describe_counter!(
+        "hookbuffer_webhooks_failed_total",
+        "Webhooks that couldn't be delivered, by destination and status"
+    );
+    describe_counter!(
+        "hookbuffer_rate_limit_retries_total",
+        "Requests retried after a 429 from the destination"
+    );
+    describe_counter!(
+        "hookbuffer_delivery_retries_total",
+        "Requests retried after a network error or 5xx from the destination"
+    );
+    describe_histogram!(
+        "hookbuffer_rate_limit_backoff_seconds",
+        Unit::Seconds,
+        "How long rate limited requests waited before retrying"
+    );
+    describe_gauge!(
+        "hookbuffer_queue_depth",
+        "Events waiting to be sent, by source and route"
+    );
+    describe_histogram!(
+        "hookbuffer_buffer_seconds",
+        Unit::Seconds,
+        "How long events waited in a queue before being sent"
+    );
+}